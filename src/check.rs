@@ -0,0 +1,1259 @@
+use crate::lex::number::{Number, SizedNum};
+use crate::parse::grammar::Document;
+use crate::parse::types::StaticType;
+use crate::parse::{
+    Ast, BinOp, ClassDef, Comprehension, Literal, Node, ObjType, Span, TemplateDef, UnOp,
+};
+use crate::resolve::{Loader, Module, Resolver};
+use std::collections::HashMap;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckErrorKind {
+    UnknownType(String),
+    UnknownVariant { ty: String, variant: String },
+    /// The value's shape (unit, tuple or struct) doesn't match its variant's declaration.
+    VariantShape(String),
+    Arity { expected: usize, found: usize },
+    MissingField(String),
+    ExtraField(String),
+    Mismatch {
+        expected: StaticType,
+        found: StaticType,
+    },
+    OutOfRange {
+        value: String,
+        ty: StaticType,
+    },
+    /// The parent named after `super` isn't a declared class with fields.
+    BadParent(String),
+    /// The class inherits from itself.
+    InheritanceCycle(String),
+    /// A subclass redeclares an inherited field with a different type. The types are boxed to
+    /// keep `CheckError` small.
+    FieldOverride {
+        field: String,
+        expected: Box<StaticType>,
+        found: Box<StaticType>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckError {
+    pub span: Span,
+    pub kind: CheckErrorKind,
+}
+
+impl CheckError {
+    pub fn new(span: Span, kind: CheckErrorKind) -> Self {
+        Self { span, kind }
+    }
+}
+
+impl Display for CheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use CheckErrorKind::*;
+        write!(f, "{}..{}: ", self.span.start, self.span.end)?;
+        match &self.kind {
+            UnknownType(t) => write!(f, "unknown type `{}`", t),
+            UnknownVariant { ty, variant } => write!(f, "`{}` has no variant `{}`", ty, variant),
+            VariantShape(v) => write!(f, "value doesn't match the shape of variant `{}`", v),
+            Arity { expected, found } => {
+                write!(f, "expected {} values, found {}", expected, found)
+            }
+            MissingField(name) => write!(f, "missing field `{}`", name),
+            ExtraField(name) => write!(f, "unknown field `{}`", name),
+            Mismatch { expected, found } => {
+                let expected: String = expected.clone().into();
+                let found: String = found.clone().into();
+                write!(f, "expected `{}`, found `{}`", expected, found)
+            }
+            OutOfRange { value, ty } => {
+                let ty: String = ty.clone().into();
+                write!(f, "`{}` doesn't fit in `{}`", value, ty)
+            }
+            BadParent(name) => write!(f, "`{}` isn't a class that can be extended", name),
+            InheritanceCycle(name) => write!(f, "`{}` inherits from itself", name),
+            FieldOverride {
+                field,
+                expected,
+                found,
+            } => {
+                let expected: String = (**expected).clone().into();
+                let found: String = (**found).clone().into();
+                write!(
+                    f,
+                    "`{}` is inherited as `{}` but redeclared as `{}`",
+                    field, expected, found
+                )
+            }
+        }
+    }
+}
+
+/// Splits `Enum::Variant` into its parts; unqualified names have no enum part.
+pub fn split_variant(name: &str) -> (Option<&str>, &str) {
+    match name.find("::") {
+        Some(i) => (Some(&name[..i]), &name[i + 2..]),
+        None => (None, name),
+    }
+}
+
+/// Finds the variant of enum `ty` that `value` constructs, checking that it has the declared
+/// shape.
+///
+/// Variants may be written qualified (`Shape::Circle(1.0)`) or, where the enum is already
+/// known, unqualified (`Circle(1.0)`).
+pub fn check_variant<'d, 's>(
+    doc: &'d Document<'s>,
+    ty: &str,
+    value: &Node<'s>,
+) -> Result<&'d ClassDef<'s>, CheckError> {
+    let variants = doc
+        .variants(ty)
+        .ok_or_else(|| CheckError::new(value.span, CheckErrorKind::UnknownType(ty.into())))?;
+    find_variant(variants, ty, value)
+}
+
+/// Finds the variant in `variants` that `value` constructs, as for `check_variant`, where the
+/// enum is known as `ty`.
+fn find_variant<'d, 's>(
+    variants: &'d [ClassDef<'s>],
+    ty: &str,
+    value: &Node<'s>,
+) -> Result<&'d ClassDef<'s>, CheckError> {
+    let name = match &value.ast {
+        Ast::Ident(name) | Ast::Tuple(Some(name), _) | Ast::Map(Some(name), _) => name,
+        _ => {
+            return Err(CheckError::new(
+                value.span,
+                CheckErrorKind::UnknownVariant {
+                    ty: ty.into(),
+                    variant: String::new(),
+                },
+            ))
+        }
+    };
+    let unknown = || {
+        CheckError::new(
+            value.span,
+            CheckErrorKind::UnknownVariant {
+                ty: ty.into(),
+                variant: name.clone(),
+            },
+        )
+    };
+    let variant = match split_variant(name) {
+        (Some(e), _) if e != ty => return Err(unknown()),
+        (_, v) => variants.iter().find(|d| d.name == v).ok_or_else(unknown)?,
+    };
+    let shape = || CheckError::new(value.span, CheckErrorKind::VariantShape(variant.name.clone()));
+    match &value.ast {
+        Ast::Ident(_) if variant.fields.is_empty() => (),
+        Ast::Tuple(_, items) if variant.is_tuple() || variant.fields.is_empty() => {
+            if items.len() != variant.fields.len() {
+                return Err(CheckError::new(
+                    value.span,
+                    CheckErrorKind::Arity {
+                        expected: variant.fields.len(),
+                        found: items.len(),
+                    },
+                ));
+            }
+        }
+        Ast::Map(_, fields) if !variant.is_tuple() => {
+            for (key, val) in fields {
+                if variant.field(key).is_none() {
+                    return Err(CheckError::new(
+                        val.span,
+                        CheckErrorKind::ExtraField(key.clone()),
+                    ));
+                }
+            }
+            for field in &variant.fields {
+                if field.default.is_none() && !fields.contains_key(&field.name) {
+                    return Err(CheckError::new(
+                        value.span,
+                        CheckErrorKind::MissingField(field.name.clone()),
+                    ));
+                }
+            }
+        }
+        _ => return Err(shape()),
+    }
+    Ok(variant)
+}
+
+/// The type of a value, as far as it can be told without a declaration to check it against.
+pub fn infer(node: &Node) -> StaticType {
+    use StaticType::*;
+    match &node.ast {
+        Ast::Literal(lit) => match lit {
+            Literal::Bool(_) => Bool,
+            Literal::Str(_) => Str,
+            Literal::Selector(_) => Selector,
+            Literal::Int(n) if n.signed => Signed(n.bits.len()),
+            Literal::Int(n) => Unsigned(n.bits.len()),
+            Literal::F32(_) => F32,
+            Literal::F64(_) => F64,
+        },
+        Ast::Interpolate(_) => Str,
+        Ast::Unary(UnOp::Not, _) => Bool,
+        Ast::Binary(op, ..) => match op {
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem => Any,
+            _ => Bool,
+        },
+        Ast::List(None, items) => List(Box::new(common(items))),
+        Ast::Tuple(None, items) => Tuple(items.iter().map(infer).collect()),
+        Ast::Map(None, fields) => Map(Box::new(Str), Box::new(common(fields.values()))),
+        Ast::If(_, then, otherwise) => common(vec![&**then, &**otherwise]),
+        Ast::ListComp(..) => List(Box::new(Any)),
+        Ast::DictComp(..) => Map(Box::new(Str), Box::new(Any)),
+        _ if node.ast.call().is_some() => Any,
+        Ast::Ident(name) | Ast::Tuple(Some(name), _) | Ast::Map(Some(name), _) => {
+            match split_variant(name) {
+                (Some(ty), _) => Class(ty.into(), Vec::new()),
+                (None, _) => match node.ast {
+                    Ast::Map(..) => Class(name.clone(), Vec::new()),
+                    _ => Any,
+                },
+            }
+        }
+        _ => Any,
+    }
+}
+
+/// The type shared by every node in `nodes`, or `Any` if they differ.
+fn common<'a, 's: 'a>(nodes: impl IntoIterator<Item = &'a Node<'s>>) -> StaticType {
+    let mut types = nodes.into_iter().map(infer);
+    let first = match types.next() {
+        Some(first) => first,
+        None => return StaticType::Any,
+    };
+    if types.all(|t| t == first) {
+        first
+    } else {
+        StaticType::Any
+    }
+}
+
+/// Whether a value inferred as `found` may be used where `expected` is declared.
+fn fits(expected: &StaticType, found: &StaticType) -> bool {
+    use StaticType::*;
+    match (expected, found) {
+        (Any, _) => true,
+        (Unsigned(_), Unsigned(_)) => true,
+        (Signed(_), Unsigned(_)) | (Signed(_), Signed(_)) => true,
+        (F32, F32) | (F32, F64) | (F64, F32) | (F64, F64) => true,
+        (F32, Unsigned(_)) | (F32, Signed(_)) | (F64, Unsigned(_)) | (F64, Signed(_)) => true,
+        (List(e), List(f)) | (Optional(e), Optional(f)) => fits(e, f),
+        (Optional(e), f) => fits(e, f),
+        (Map(ek, ev), Map(fk, fv)) => fits(ek, fk) && fits(ev, fv),
+        (Tuple(es), Tuple(_)) if es.is_empty() => true,
+        (Tuple(es), Tuple(fs)) => {
+            es.len() == fs.len() && es.iter().zip(fs).all(|(e, f)| fits(e, f))
+        }
+        (Class(e, e_args), Class(f, f_args)) => {
+            e == f && (e_args.is_empty() || f_args.is_empty() || e_args == f_args)
+        }
+        (e, f) => e == f,
+    }
+}
+
+/// The documents a `Loader` has loaded, by URL.
+pub(crate) trait Modules {
+    fn module(&self, url: &str) -> Option<&Module>;
+}
+
+impl<R: Resolver> Modules for Loader<R> {
+    fn module(&self, url: &str) -> Option<&Module> {
+        Loader::module(self, url)
+    }
+}
+
+/// Finds the document that declares the class or enum `name`, as seen from the document at
+/// `url`, and the name it's declared with there.
+pub(crate) fn declaration<'d>(
+    modules: &'d dyn Modules,
+    url: &str,
+    name: &str,
+) -> Option<(&'d Module, String)> {
+    let module = modules.module(url)?;
+    if module.doc.class(name).is_some() || module.doc.variants(name).is_some() {
+        return Some((module, name.to_string()));
+    }
+    let (import, name) = module.names.get(name)?;
+    declaration(modules, import, name)
+}
+
+/// Where a checker made with `Checker::in_module` finds declarations.
+#[derive(Clone, Copy)]
+struct Loaded<'d> {
+    modules: &'d dyn Modules,
+    /// The URL of the document whose declarations are being checked against.
+    url: &'d str,
+    /// The URL of the document the checker was made for.
+    home: &'d str,
+}
+
+/// Checks a document's values against its class, type and enum declarations.
+pub struct Checker<'d, 's> {
+    doc: &'d Document<'s>,
+    /// Set for a document loaded with a `Loader`, so classes and enums it imports with `use`
+    /// can be found.
+    loaded: Option<Loaded<'d>>,
+    errors: Vec<CheckError>,
+    /// Aliases currently being expanded, so `type A = A;` can't recurse forever.
+    aliases: Vec<String>,
+    /// Integer literals resized to their declared (or minimal) width, by span.
+    coerced: HashMap<Span, SizedNum>,
+    /// Names bound by the comprehensions enclosing the node being checked, innermost last.
+    locals: Vec<(String, StaticType)>,
+}
+
+impl<'d, 's> Checker<'d, 's> {
+    pub fn new(doc: &'d Document<'s>) -> Self {
+        Self {
+            doc,
+            loaded: None,
+            errors: Vec::new(),
+            aliases: Vec::new(),
+            coerced: HashMap::new(),
+            locals: Vec::new(),
+        }
+    }
+
+    /// A checker for a loaded `module`, which also finds the classes and enums it imports.
+    pub fn in_module<R: Resolver>(loader: &'d Loader<R>, module: &'d Module) -> Self {
+        let mut checker = Self::new(&module.doc);
+        checker.loaded = Some(Loaded {
+            modules: loader,
+            url: &module.url,
+            home: &module.url,
+        });
+        checker
+    }
+
+    /// Checks every field default and the document's root value.
+    pub fn check_all(&mut self) {
+        let doc = self.doc;
+        for node in &doc.defs {
+            let defs = match &node.ast {
+                Ast::Def(_, def) => std::slice::from_ref(def),
+                Ast::Enum(_, _, variants) => &variants[..],
+                Ast::Template(def) => {
+                    self.check_template(def);
+                    continue;
+                }
+                _ => continue,
+            };
+            if let Ast::Def(ObjType::Super, def) = &node.ast {
+                self.check_parent(def);
+            }
+            for def in defs {
+                // Defaults have to suit any instantiation of the class.
+                let params = def
+                    .params
+                    .iter()
+                    .map(|p| (p.clone(), StaticType::Any))
+                    .collect();
+                for field in &def.fields {
+                    if let Some(default) = &field.default {
+                        self.check(default, &field.ty.substitute(&params));
+                    }
+                }
+            }
+        }
+        if let Some(root) = &doc.root {
+            self.check(root, &StaticType::Any);
+        }
+    }
+
+    /// Checks a template's defaults against its parameters' types, and its body with the
+    /// parameters bound to them.
+    fn check_template(&mut self, def: &TemplateDef<'s>) {
+        for param in &def.params {
+            if let Some(default) = &param.default {
+                self.check(default, &param.ty);
+            }
+            self.locals.push((param.name.clone(), param.ty.clone()));
+        }
+        self.check(&def.body, &StaticType::Any);
+        self.locals.clear();
+    }
+
+    /// Checks the arguments of a call to the template `def` against its parameters.
+    fn check_call(&mut self, span: Span, def: &TemplateDef<'s>, args: &[Node<'s>]) {
+        if args.len() < def.required() || args.len() > def.params.len() {
+            let expected = if args.len() < def.required() {
+                def.required()
+            } else {
+                def.params.len()
+            };
+            return self.error(
+                span,
+                CheckErrorKind::Arity {
+                    expected,
+                    found: args.len(),
+                },
+            );
+        }
+        for (param, arg) in def.params.iter().zip(args) {
+            self.check(arg, &param.ty);
+        }
+    }
+
+    /// Checks that `def`'s parent can be extended, and that redeclared fields keep the types
+    /// they're inherited with.
+    fn check_parent(&mut self, def: &ClassDef<'s>) {
+        let parent = match &def.parent {
+            Some(parent) => parent,
+            None => return,
+        };
+        match self.doc.class(parent) {
+            Some(p) if p.alias.is_none() => (),
+            _ => return self.error(def.span, CheckErrorKind::BadParent(parent.clone())),
+        }
+        if self.doc.is_subclass(parent, &def.name) {
+            return self.error(def.span, CheckErrorKind::InheritanceCycle(def.name.clone()));
+        }
+        for field in &def.fields {
+            if let Some(inherited) = self
+                .doc
+                .fields(parent)
+                .into_iter()
+                .find(|f| f.name == field.name)
+            {
+                if inherited.ty != field.ty {
+                    self.error(
+                        field.span,
+                        CheckErrorKind::FieldOverride {
+                            field: field.name.clone(),
+                            expected: Box::new(inherited.ty.clone()),
+                            found: Box::new(field.ty.clone()),
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    /// The resized integer literals, or every error found.
+    pub fn finish(self) -> Result<HashMap<Span, SizedNum>, Vec<CheckError>> {
+        if self.errors.is_empty() {
+            Ok(self.coerced)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn error(&mut self, span: Span, kind: CheckErrorKind) {
+        self.errors.push(CheckError::new(span, kind));
+    }
+
+    fn mismatch(&mut self, node: &Node<'s>, expected: &StaticType) {
+        self.error(
+            node.span,
+            CheckErrorKind::Mismatch {
+                expected: expected.clone(),
+                found: infer(node),
+            },
+        );
+    }
+
+    /// Checks `node` against `expected`, recording any errors.
+    pub fn check(&mut self, node: &Node<'s>, expected: &StaticType) {
+        use StaticType::*;
+        if let Some((name, args)) = node.ast.call() {
+            if let Some(def) = self.doc.template(name) {
+                return self.check_call(node.span, def, args);
+            }
+        }
+        // References, expressions and calls can only be checked once they've been evaluated.
+        if matches!(
+            node.ast,
+            Ast::Ref(..) | Ast::Binary(..) | Ast::Unary(..) | Ast::Pipe(..)
+        ) || node.ast.call().is_some()
+        {
+            return;
+        }
+        match (&node.ast, expected) {
+            (Ast::Ident(name), _) if self.local(name).is_some() => {
+                let found = self.local(name).unwrap();
+                if !fits(expected, &found) {
+                    self.error(
+                        node.span,
+                        CheckErrorKind::Mismatch {
+                            expected: expected.clone(),
+                            found,
+                        },
+                    );
+                }
+                return;
+            }
+            (Ast::If(cond, then, otherwise), _) => {
+                self.check(cond, &Bool);
+                self.check(then, expected);
+                return self.check(otherwise, expected);
+            }
+            (Ast::ListComp(item, comp), List(t)) => {
+                return self.comprehension(comp, |c| c.check(item, t))
+            }
+            (Ast::ListComp(item, comp), Any) => {
+                return self.comprehension(comp, |c| c.check(item, &Any))
+            }
+            // Generated keys are converted to strings when evaluated, so only values are checked.
+            (Ast::DictComp(_, value, comp), Map(_, v)) => {
+                return self.comprehension(comp, |c| c.check(value, v))
+            }
+            (Ast::DictComp(_, value, comp), Any) => {
+                return self.comprehension(comp, |c| c.check(value, &Any))
+            }
+            _ => (),
+        }
+        match expected {
+            Any => self.walk(node),
+            Class(name, args) => self.check_class(node, name, args),
+            List(t) => match &node.ast {
+                Ast::List(None, items) => {
+                    for item in items {
+                        self.check(item, t);
+                    }
+                }
+                _ => self.mismatch(node, expected),
+            },
+            Map(k, v) => match &node.ast {
+                Ast::Map(None, fields) => {
+                    if !fits(k, &Str) {
+                        self.error(
+                            node.span,
+                            CheckErrorKind::Mismatch {
+                                expected: (**k).clone(),
+                                found: Str,
+                            },
+                        );
+                    }
+                    for val in fields.values() {
+                        self.check(val, v);
+                    }
+                }
+                _ => self.mismatch(node, expected),
+            },
+            Tuple(ts) => match &node.ast {
+                Ast::Tuple(None, _) | Ast::List(None, _) if ts.is_empty() => (),
+                Ast::Tuple(None, items) | Ast::List(None, items) => {
+                    if items.len() != ts.len() {
+                        return self.error(
+                            node.span,
+                            CheckErrorKind::Arity {
+                                expected: ts.len(),
+                                found: items.len(),
+                            },
+                        );
+                    }
+                    for (item, t) in items.iter().zip(ts) {
+                        self.check(item, t);
+                    }
+                }
+                _ => self.mismatch(node, expected),
+            },
+            Optional(t) => match &node.ast {
+                Ast::Ident(name) if name == "None" => (),
+                Ast::Tuple(Some(name), items) if name == "Some" && items.len() == 1 => {
+                    self.check(&items[0], t)
+                }
+                _ => self.check(node, t),
+            },
+            expected => {
+                let width = match expected {
+                    Unsigned(bits) => Some((false, *bits)),
+                    Signed(bits) => Some((true, *bits)),
+                    _ => None,
+                };
+                if let (Some((signed, bits)), Ast::Literal(Literal::Int(n))) = (width, &node.ast) {
+                    match n.resize(signed, bits) {
+                        Some(n) => {
+                            self.coerced.insert(node.span, n);
+                        }
+                        None => self.error(
+                            node.span,
+                            CheckErrorKind::OutOfRange {
+                                value: Number::from(n.clone()).to_string(),
+                                ty: expected.clone(),
+                            },
+                        ),
+                    }
+                    return;
+                }
+                if !fits(expected, &infer(node)) {
+                    self.mismatch(node, expected);
+                }
+            }
+        }
+    }
+
+    /// Checks a value of unconstrained type: any instances of classes or enums within it are
+    /// still checked against their declarations, and must have one.
+    fn walk(&mut self, node: &Node<'s>) {
+        if let StaticType::Class(name, _) = infer(node) {
+            return self.check_class(node, &name, &[]);
+        }
+        match &node.ast {
+            Ast::Literal(Literal::Int(n)) => {
+                self.coerced.insert(node.span, n.minimal());
+            }
+            Ast::Tuple(Some(name), _) if self.doc.template(name).is_some() => {
+                self.check(node, &StaticType::Any)
+            }
+            Ast::List(_, items) | Ast::Tuple(_, items) => {
+                for item in items {
+                    self.walk(item);
+                }
+            }
+            Ast::Map(_, fields) | Ast::Mod(_, fields) => {
+                for val in fields.values() {
+                    self.walk(val);
+                }
+            }
+            Ast::If(..) | Ast::ListComp(..) | Ast::DictComp(..) => {
+                self.check(node, &StaticType::Any)
+            }
+            _ => (),
+        }
+    }
+
+    /// The type of a name bound by an enclosing comprehension.
+    fn local(&self, name: &str) -> Option<StaticType> {
+        self.locals
+            .iter()
+            .rev()
+            .find(|(local, _)| local == name)
+            .map(|(_, ty)| ty.clone())
+    }
+
+    /// Checks a comprehension's iterable and filter, then runs `check` with its names bound to
+    /// the key and item types of the iterable, as far as they can be inferred.
+    fn comprehension(&mut self, comp: &Comprehension<'s>, check: impl FnOnce(&mut Self)) {
+        use StaticType::*;
+        self.walk(&comp.items);
+        // A single name is bound to each item of a list, but to each key of a dict.
+        let types = match (infer(&comp.items), comp.vars.len()) {
+            (List(t), 1) => vec![*t],
+            (List(t), _) => vec![Unsigned(64), *t],
+            (Map(k, _), 1) => vec![*k],
+            (Map(k, v), _) => vec![*k, *v],
+            _ => Vec::new(),
+        };
+        let depth = self.locals.len();
+        let types = types.into_iter().chain(std::iter::repeat(Any));
+        self.locals.extend(comp.vars.iter().cloned().zip(types));
+        if let Some(filter) = &comp.filter {
+            self.check(filter, &Bool);
+        }
+        check(self);
+        self.locals.truncate(depth);
+    }
+
+    /// Binds `def`'s type parameters to `args`; with no arguments, every parameter is `Any`.
+    fn bind(
+        &mut self,
+        def: &ClassDef<'s>,
+        args: &[StaticType],
+        span: Span,
+    ) -> Option<HashMap<String, StaticType>> {
+        if args.is_empty() {
+            return Some(
+                def.params
+                    .iter()
+                    .map(|p| (p.clone(), StaticType::Any))
+                    .collect(),
+            );
+        }
+        if args.len() != def.params.len() {
+            self.error(
+                span,
+                CheckErrorKind::Arity {
+                    expected: def.params.len(),
+                    found: args.len(),
+                },
+            );
+            return None;
+        }
+        Some(def.params.iter().cloned().zip(args.iter().cloned()).collect())
+    }
+
+    fn check_class(&mut self, node: &Node<'s>, name: &str, args: &[StaticType]) {
+        if !self.declared(name, |c, declared| {
+            c.check_declared(node, name, declared, args)
+        }) {
+            self.error(node.span, CheckErrorKind::UnknownType(name.into()));
+        }
+    }
+
+    /// Runs `f` in the document that declares the class or enum `name`, with the name it's
+    /// declared with there. Returns `false` if there's no such declaration.
+    fn declared(&mut self, name: &str, f: impl FnOnce(&mut Self, &str)) -> bool {
+        let loaded = match self.loaded {
+            Some(loaded) => loaded,
+            None if self.doc.class(name).is_some() || self.doc.variants(name).is_some() => {
+                f(self, name);
+                return true;
+            }
+            None => return false,
+        };
+        // A class is checked in the document that declares it, where the types of its fields
+        // are named. Values may have been written in the document being checked, so names are
+        // looked up from there as well.
+        let found = declaration(loaded.modules, loaded.url, name)
+            .or_else(|| declaration(loaded.modules, loaded.home, name));
+        let (module, declared) = match found {
+            Some(found) => found,
+            None => return false,
+        };
+        let saved = self.doc;
+        self.doc = &module.doc;
+        self.loaded = Some(Loaded {
+            url: &module.url,
+            ..loaded
+        });
+        f(self, &declared);
+        self.doc = saved;
+        self.loaded = Some(loaded);
+        true
+    }
+
+    /// Checks `node` against the class or enum `declared` in `doc`, which it names `name`.
+    fn check_declared(&mut self, node: &Node<'s>, name: &str, declared: &str, args: &[StaticType]) {
+        if let Some(def) = self.doc.class(declared) {
+            let params = match self.bind(def, args, node.span) {
+                Some(params) => params,
+                None => return,
+            };
+            if let Some(alias) = &def.alias {
+                if self.aliases.iter().any(|a| a == declared) {
+                    return;
+                }
+                self.aliases.push(declared.into());
+                self.check(node, &alias.substitute(&params));
+                self.aliases.pop();
+                return;
+            }
+            match &node.ast {
+                Ast::Map(None, fields) => self.check_fields(declared, &params, fields, node.span),
+                Ast::Map(Some(n), fields) if n == name => {
+                    self.check_fields(declared, &params, fields, node.span)
+                }
+                // An instance of a subclass can stand in for its parent, and is checked against
+                // its own class.
+                Ast::Map(Some(n), fields) if self.doc.is_subclass(n, declared) => {
+                    self.check_fields(n, &params, fields, node.span)
+                }
+                _ => self.mismatch(node, &StaticType::Class(name.into(), args.to_vec())),
+            }
+        } else if let Some(variants) = self.doc.variants(declared) {
+            let variant = match find_variant(variants, name, node) {
+                Ok(variant) => variant,
+                Err(e) => return self.errors.push(e),
+            };
+            match &node.ast {
+                Ast::Tuple(_, items) => {
+                    for (item, field) in items.iter().zip(&variant.fields) {
+                        self.check(item, &field.ty);
+                    }
+                }
+                Ast::Map(_, fields) => {
+                    for (key, val) in fields {
+                        self.check(val, &variant.field(key).unwrap().ty);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Resizes the integers in an evaluated `value` to the widths `ty` declares for them.
+    ///
+    /// Instances of classes are left as they are, since their own fields are resized when
+    /// they're made.
+    pub fn resize(&mut self, value: &mut Node<'s>, ty: &StaticType) -> Result<(), CheckError> {
+        use StaticType::*;
+        let span = value.span;
+        match (&mut value.ast, ty) {
+            (Ast::Literal(Literal::Int(n)), Unsigned(bits)) => resize(span, n, false, *bits)?,
+            (Ast::Literal(Literal::Int(n)), Signed(bits)) => resize(span, n, true, *bits)?,
+            (Ast::List(None, items), List(t)) => {
+                for item in items {
+                    self.resize(item, t)?;
+                }
+            }
+            (Ast::Map(None, fields), Map(_, t)) => {
+                for val in fields.values_mut() {
+                    self.resize(val, t)?;
+                }
+            }
+            (Ast::Tuple(None, items), Tuple(ts)) | (Ast::List(None, items), Tuple(ts)) => {
+                for (item, t) in items.iter_mut().zip(ts) {
+                    self.resize(item, t)?;
+                }
+            }
+            (Ast::Tuple(Some(name), items), Optional(t)) if name == "Some" && items.len() == 1 => {
+                self.resize(&mut items[0], t)?
+            }
+            (_, Optional(t)) => self.resize(value, t)?,
+            (_, Class(name, args)) => {
+                let mut result = Ok(());
+                self.declared(name, |c, declared| {
+                    result = c.resize_declared(value, name, declared, args)
+                });
+                result?
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Resizes the integers in `value` to the widths the type alias or enum `declared` gives
+    /// them. The variant of an enum that `value` constructs is known as `name`.
+    fn resize_declared(
+        &mut self,
+        value: &mut Node<'s>,
+        name: &str,
+        declared: &str,
+        args: &[StaticType],
+    ) -> Result<(), CheckError> {
+        if let Some(def) = self.doc.class(declared) {
+            let alias = match &def.alias {
+                Some(alias) if !self.aliases.iter().any(|a| a == declared) => alias,
+                _ => return Ok(()),
+            };
+            let params = match self.bind(def, args, value.span) {
+                Some(params) => params,
+                None => return Ok(()),
+            };
+            self.aliases.push(declared.into());
+            let result = self.resize(value, &alias.substitute(&params));
+            self.aliases.pop();
+            return result;
+        }
+        // The fields of struct-like variants are resized when they're made, like a class's.
+        let variant = match (self.doc.variants(declared), &value.ast) {
+            (Some(variants), Ast::Tuple(Some(_), _)) => find_variant(variants, name, value).ok(),
+            _ => None,
+        };
+        if let (Some(variant), Ast::Tuple(_, items)) = (variant, &mut value.ast) {
+            for (item, field) in items.iter_mut().zip(&variant.fields) {
+                self.resize(item, &field.ty)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the fields of an instance of `class`, including those it inherits.
+    fn check_fields(
+        &mut self,
+        class: &str,
+        params: &HashMap<String, StaticType>,
+        fields: &HashMap<String, Node<'s>>,
+        span: Span,
+    ) {
+        // Parameters of the classes `class` inherits from are unconstrained.
+        let mut params = params.clone();
+        let defs = self.doc.fields(class);
+        for def in self.doc.ancestors(class).into_iter().skip(1) {
+            for p in &def.params {
+                params.entry(p.clone()).or_insert(StaticType::Any);
+            }
+        }
+        for (key, val) in fields {
+            match defs.iter().find(|f| f.name == *key) {
+                Some(field) => self.check(val, &field.ty.substitute(&params)),
+                None => self.error(val.span, CheckErrorKind::ExtraField(key.clone())),
+            }
+        }
+        for field in defs {
+            if field.default.is_none() && !fields.contains_key(&field.name) {
+                self.error(span, CheckErrorKind::MissingField(field.name.clone()));
+            }
+        }
+    }
+}
+
+/// Resizes the integer `n` to `bits` wide, or fails if it doesn't fit.
+fn resize(span: Span, n: &mut SizedNum, signed: bool, bits: usize) -> Result<(), CheckError> {
+    match n.resize(signed, bits) {
+        Some(sized) => {
+            *n = sized;
+            Ok(())
+        }
+        None => {
+            let ty = if signed {
+                StaticType::Signed(bits)
+            } else {
+                StaticType::Unsigned(bits)
+            };
+            let value = Number::from(n.clone()).to_string();
+            Err(CheckError::new(
+                span,
+                CheckErrorKind::OutOfRange { value, ty },
+            ))
+        }
+    }
+}
+
+/// Checks `doc`, then resizes its integer literals to their declared widths.
+pub fn check_document(doc: &mut Document) -> Result<(), Vec<CheckError>> {
+    let mut coerced = {
+        let mut checker = Checker::new(doc);
+        checker.check_all();
+        checker.finish()?
+    };
+    let mut resize = |node: &mut Node| {
+        if let Ast::Literal(Literal::Int(n)) = &mut node.ast {
+            if let Some(sized) = coerced.remove(&node.span) {
+                *n = sized;
+            }
+        }
+    };
+    for node in doc.defs.iter_mut().chain(doc.root.as_mut()) {
+        node.visit_mut(&mut resize);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolve::MemoryResolver;
+
+    #[test]
+    fn variants() {
+        let doc = Document::parse(
+            r#"
+            enum Shape { Empty, Circle(f32), Rect { w: f32, h: f32 } }
+            [Shape::Empty, Circle(2.0), Shape::Rect { w: 1.0, h: 3.0 }, Shape::Circle(1.0, 2.0), Rect { w: 1.0 }, Shape::Square(1.0)]
+            "#,
+        )
+        .unwrap();
+        let items = match &doc.root.as_ref().unwrap().ast {
+            Ast::List(_, items) => items,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(check_variant(&doc, "Shape", &items[0]).unwrap().name, "Empty");
+        assert_eq!(check_variant(&doc, "Shape", &items[1]).unwrap().name, "Circle");
+        assert_eq!(check_variant(&doc, "Shape", &items[2]).unwrap().name, "Rect");
+        assert_eq!(
+            check_variant(&doc, "Shape", &items[3]).unwrap_err().kind,
+            CheckErrorKind::Arity {
+                expected: 1,
+                found: 2
+            }
+        );
+        assert_eq!(
+            check_variant(&doc, "Shape", &items[4]).unwrap_err().kind,
+            CheckErrorKind::MissingField("h".into())
+        );
+        assert_eq!(
+            check_variant(&doc, "Shape", &items[5]).unwrap_err().kind,
+            CheckErrorKind::UnknownVariant {
+                ty: "Shape".into(),
+                variant: "Shape::Square".into()
+            }
+        );
+    }
+
+    #[test]
+    fn imports() {
+        let files: MemoryResolver = vec![
+            (
+                "ui.stn",
+                r#"
+                use icons.stn::{Icon};
+                pub class Button { label: String, width: u16 = 80, icon: Icon = Icon { name: "none" } }
+                pub enum Align { Left, Right }
+                "#,
+            ),
+            ("icons.stn", "pub class Icon { name: String }"),
+            (
+                "main.stn",
+                r#"use ui.stn::{Button, Align as Side};
+                [
+                    Button { label: "OK", icon: { name: "check" } },
+                    Side::Left,
+                    Button { label: "No", width: 70000 },
+                    Missing { label: "?" },
+                ]"#,
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files);
+        let url = loader.load("main.stn").unwrap();
+        let mut checker = Checker::in_module(&loader, loader.module(&url).unwrap());
+        checker.check_all();
+        let mut errors = checker.finish().unwrap_err();
+        errors.sort_by_key(|e| e.span.start);
+        let kinds: Vec<_> = errors.into_iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                CheckErrorKind::OutOfRange {
+                    value: "70000".into(),
+                    ty: StaticType::Unsigned(16),
+                },
+                CheckErrorKind::UnknownType("Missing".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn classes() {
+        let mut doc = Document::parse(
+            r#"
+            class Icon { name: String }
+            class Button {
+                label: String,
+                width: u16 = 80,
+                icon: Icon,
+                extra: Any = true,
+            }
+            [
+                Button { label: "OK", icon: { name: "check" } },
+                Button { label: 12, icon: Icon { name: "x" }, colour: "red" },
+                Button { width: -3, icon: "cross" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let mut errors = check_document(&mut doc).unwrap_err();
+        errors.sort_by_key(|e| e.span.start);
+        let kinds: Vec<_> = errors.into_iter().map(|e| e.kind).collect();
+        assert_eq!(kinds.len(), 5);
+        assert!(kinds.contains(&CheckErrorKind::Mismatch {
+            expected: StaticType::Str,
+            found: StaticType::Unsigned(4),
+        }));
+        assert!(kinds.contains(&CheckErrorKind::ExtraField("colour".into())));
+        assert!(kinds.contains(&CheckErrorKind::MissingField("label".into())));
+        assert!(kinds.contains(&CheckErrorKind::OutOfRange {
+            value: "-3".into(),
+            ty: StaticType::Unsigned(16),
+        }));
+        assert!(kinds.contains(&CheckErrorKind::Mismatch {
+            expected: StaticType::Class("Icon".into(), Vec::new()),
+            found: StaticType::Str,
+        }));
+    }
+
+    #[test]
+    fn widths() {
+        let mut doc = Document::parse(
+            r#"
+            class Pin { id: u7, offset: i12, mask: u8 = 255, pull: u1 }
+            { pins: [Pin { id: 5, offset: -2048, pull: 1 }], count: 3 }
+            "#,
+        )
+        .unwrap();
+        check_document(&mut doc).unwrap();
+        let mut widths = Vec::new();
+        doc.root.as_mut().unwrap().visit_mut(&mut |node| {
+            if let Ast::Literal(Literal::Int(n)) = &node.ast {
+                widths.push(n.bits.len());
+            }
+        });
+        widths.sort();
+        assert_eq!(widths, vec![1, 2, 7, 12]);
+
+        let mut doc = Document::parse(
+            r#"
+            class Pin { id: u7, offset: i12 }
+            [Pin { id: 128, offset: 0 }, Pin { id: 1, offset: -2049 }, Pin { id: -1, offset: 0 }]
+            "#,
+        )
+        .unwrap();
+        let errors = check_document(&mut doc).unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(
+            errors
+                .iter()
+                .filter(|e| matches!(e.kind, CheckErrorKind::OutOfRange { .. }))
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    fn generics() {
+        let mut doc = Document::parse(
+            r#"
+            class Pair<A, B> { first: A, second: B }
+            type Pins = List<Pair<String, u4>>;
+            class Board {
+                pins: Pins,
+                labels: Map<String, Option<String>>,
+                origin: Tuple<i8, i8> = (0, 0),
+                extra: Tuple = (),
+            }
+            Board {
+                pins: [{ first: "led", second: 13 }, Pair { first: 2, second: 16 }],
+                labels: { a: "x", b: None, c: Some(3) },
+                origin: (1, 2, 3),
+                extra: ("a", 1, 2.5),
+            }
+            "#,
+        )
+        .unwrap();
+        let kinds: Vec<_> = check_document(&mut doc)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.kind)
+            .collect();
+        assert_eq!(kinds.len(), 4);
+        // `first: 2` and `c: Some(3)`
+        let not_str = CheckErrorKind::Mismatch {
+            expected: StaticType::Str,
+            found: StaticType::Unsigned(2),
+        };
+        assert_eq!(kinds.iter().filter(|k| **k == not_str).count(), 2);
+        assert!(kinds.contains(&CheckErrorKind::OutOfRange {
+            value: "16".into(),
+            ty: StaticType::Unsigned(4),
+        }));
+        assert!(kinds.contains(&CheckErrorKind::Arity {
+            expected: 2,
+            found: 3,
+        }));
+    }
+
+    #[test]
+    fn inheritance() {
+        let mut doc = Document::parse(
+            r#"
+            class Button { label: String, width: u16 = 80 }
+            class Toggle : super Button { width: u16 = 40, on: bool = false }
+            class Wide : super Toggle { width: u8 }
+            class Loop : super Loop {}
+            class Panel { button: Button }
+            [
+                Panel { button: Toggle { label: "Dark mode", on: true } },
+                Panel { button: Toggle { on: 1 } },
+            ]
+            "#,
+        )
+        .unwrap();
+        let toggle: Vec<_> = doc
+            .fields("Toggle")
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect();
+        assert_eq!(toggle, vec!["label", "width", "on"]);
+        assert!(doc.is_subclass("Wide", "Button"));
+        assert!(!doc.is_subclass("Button", "Toggle"));
+
+        let kinds: Vec<_> = check_document(&mut doc)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.kind)
+            .collect();
+        assert_eq!(kinds.len(), 4);
+        assert!(kinds.contains(&CheckErrorKind::FieldOverride {
+            field: "width".into(),
+            expected: Box::new(StaticType::Unsigned(16)),
+            found: Box::new(StaticType::Unsigned(8)),
+        }));
+        assert!(kinds.contains(&CheckErrorKind::InheritanceCycle("Loop".into())));
+        assert!(kinds.contains(&CheckErrorKind::MissingField("label".into())));
+        assert!(kinds.contains(&CheckErrorKind::Mismatch {
+            expected: StaticType::Bool,
+            found: StaticType::Unsigned(1),
+        }));
+    }
+
+    #[test]
+    fn unknown_classes() {
+        let mut doc = Document::parse(
+            r#"
+            class Button { label: String }
+            [Button { label: "a" }, Buton { label: "b" }, { nested: [Shape::Circle(1.0)] }]
+            "#,
+        )
+        .unwrap();
+        let kinds: Vec<_> = check_document(&mut doc)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                CheckErrorKind::UnknownType("Buton".into()),
+                CheckErrorKind::UnknownType("Shape".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn comprehensions() {
+        let mut doc = Document::parse(
+            r#"
+            class Config { names: List<String>, ports: List<u16>, env: Map<String, u16>, mode: String }
+            Config {
+                names: [x for x in ["a", "b"] if x != "b"],
+                ports: [x for x in ["a", "b"]],
+                env: { k: "x" for k in { a: 1 } },
+                mode: if $prod { "fast" } else { 1 },
+            }
+            "#,
+        )
+        .unwrap();
+        let kinds: Vec<_> = check_document(&mut doc)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.kind)
+            .collect();
+        let mismatch = |expected, found| CheckErrorKind::Mismatch { expected, found };
+        assert_eq!(kinds.len(), 3);
+        let ports = mismatch(StaticType::Unsigned(16), StaticType::Str);
+        assert_eq!(kinds.iter().filter(|&k| *k == ports).count(), 2);
+        assert!(kinds.contains(&mismatch(StaticType::Str, StaticType::Unsigned(1))));
+    }
+
+    #[test]
+    fn templates() {
+        let mut doc = Document::parse(
+            r#"
+            class Card { title: String, width: u8 }
+            card(title: String, width: u8 = 300) = Card { title: $title, width: $width };
+            broken(title) = Card { title: $title }
+            [card("a"), card(1), card("a", 2, 3), { nested: card("b", -1) }]
+            "#,
+        )
+        .unwrap();
+        let kinds: Vec<_> = check_document(&mut doc)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                CheckErrorKind::OutOfRange {
+                    value: "300".into(),
+                    ty: StaticType::Unsigned(8),
+                },
+                CheckErrorKind::MissingField("width".into()),
+                CheckErrorKind::Mismatch {
+                    expected: StaticType::Str,
+                    found: StaticType::Unsigned(1),
+                },
+                CheckErrorKind::Arity {
+                    expected: 2,
+                    found: 3,
+                },
+                CheckErrorKind::OutOfRange {
+                    value: "-1".into(),
+                    ty: StaticType::Unsigned(8),
+                },
+            ]
+        );
+    }
+}
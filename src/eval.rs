@@ -0,0 +1,2179 @@
+use crate::check::{declaration, split_variant, CheckError, CheckErrorKind, Checker};
+use crate::lex::number::{Number, SizedNum};
+use crate::parse::grammar::Document;
+use crate::parse::types::StaticType;
+use crate::parse::{
+    Ast, BinOp, Comprehension, FieldDef, Literal, Node, Span, Strategy, TemplateDef, UnOp,
+};
+use crate::resolve::{Loader, MemoryResolver, Module, Resolver};
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::ops::{Add, Div, Mul, Rem, Sub};
+
+pub mod functions;
+pub mod host;
+
+use self::functions::{Functions, Param};
+use self::host::HostFunction;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalErrorKind {
+    UnknownModule(String),
+    /// Nothing in the document or its imports binds this name.
+    UnknownName(String),
+    /// A name bound by a `use` whose document was never loaded, named by its target.
+    Unloaded(String),
+    /// Each name in a chain of definitions that depends on itself.
+    Cycle(Vec<String>),
+    /// A `mod` override or merge strategy doesn't fit the value it's applied to.
+    Shape,
+    /// A key in an override for a list isn't an index.
+    InvalidIndex(String),
+    IndexOutOfRange {
+        index: usize,
+        len: usize,
+    },
+    /// `-key` or `key @strategy: value` outside of a `mod` override.
+    UnexpectedOverride,
+    /// A reference's path leads outside of the value it starts from.
+    BadPath(String),
+    /// A reference through a document's alias names a value it doesn't declare `pub`.
+    Private(String),
+    /// A `${...}` reference in a string isn't a string, number or boolean.
+    NotText(String),
+    /// An instance sets a field its class doesn't declare.
+    UnknownField {
+        class: String,
+        field: String,
+    },
+    /// An instance leaves out a field that has no default.
+    MissingField {
+        class: String,
+        field: String,
+    },
+    /// An integer that doesn't fit the width declared for the field it's bound to.
+    OutOfRange {
+        field: String,
+        value: String,
+        ty: String,
+    },
+    /// An operator applied to values it isn't defined for, described by their kinds.
+    Operands {
+        op: String,
+        found: String,
+    },
+    /// Integer arithmetic whose result doesn't fit in 128 bits.
+    Overflow(String),
+    DivideByZero,
+    UnknownFunction(String),
+    /// A function called with too few or too many arguments.
+    Arity {
+        function: String,
+        expected: usize,
+        /// Whether the function takes any number of arguments past `expected`.
+        variadic: bool,
+        found: usize,
+    },
+    /// A function's argument isn't the type it takes, counting from 1.
+    Argument {
+        function: String,
+        index: usize,
+        expected: String,
+        found: String,
+    },
+    /// A function failed with the given reason.
+    Call {
+        function: String,
+        message: String,
+    },
+    /// A comprehension over something other than a list, tuple or dict.
+    NotIterable(String),
+    /// An `if` condition or comprehension filter that isn't a boolean.
+    Condition(String),
+    /// A key generated by a dict comprehension that isn't a string, number or name.
+    KeyType(String),
+    /// Evaluation went past one of the evaluator's `Limits`, which was set to `max`.
+    Limit {
+        limit: Limit,
+        max: usize,
+    },
+}
+
+/// A resource bounded by `Limits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Depth,
+    Steps,
+    Size,
+    Calls,
+}
+
+/// Bounds on the work an `Evaluator` does, so that untrusted documents can't run forever or
+/// exhaust memory. `None` leaves a resource unbounded.
+///
+/// By default only template calls are bounded, since a template that calls itself forever
+/// would otherwise overflow the stack.
+///
+/// Imports are bounded by the `Loader` instead: see `Loader::allow_imports`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// How deeply values, references and template calls may nest while being evaluated.
+    pub depth: Option<usize>,
+    /// How many nodes may be evaluated in total, over every call to the evaluator.
+    pub steps: Option<usize>,
+    /// How big any one value may be, counting each node in it and each byte of its strings and
+    /// dict keys.
+    pub size: Option<usize>,
+    /// How deeply template calls may nest, 128 by default. Each call takes a few kilobytes of
+    /// stack, more in a debug build, so raise this only on a thread with a stack to match.
+    pub calls: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            depth: None,
+            steps: None,
+            size: None,
+            calls: Some(128),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalError {
+    /// The document the error occurred in, if known.
+    pub url: Option<String>,
+    pub span: Span,
+    pub kind: EvalErrorKind,
+}
+
+impl EvalError {
+    pub fn new(span: Span, kind: EvalErrorKind) -> Self {
+        Self {
+            url: None,
+            span,
+            kind,
+        }
+    }
+
+    /// Sets the document the error occurred in, unless it's already known.
+    fn at(mut self, url: &str) -> Self {
+        self.url.get_or_insert_with(|| url.to_string());
+        self
+    }
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use EvalErrorKind::*;
+        if let Some(url) = &self.url {
+            write!(f, "{}:", url)?;
+        }
+        write!(f, "{}..{}: ", self.span.start, self.span.end)?;
+        match &self.kind {
+            UnknownModule(url) => write!(f, "{} isn't loaded", url),
+            UnknownName(name) => write!(f, "`{}` isn't defined", name),
+            Unloaded(target) => write!(f, "`use {};` was never loaded", target),
+            Cycle(names) => write!(f, "`{}` depends on itself", names.join("` -> `")),
+            Shape => write!(f, "the override doesn't fit the value it modifies"),
+            InvalidIndex(key) => write!(f, "`{}` isn't a list index", key),
+            IndexOutOfRange { index, len } => {
+                write!(f, "index {} is out of range for a list of {}", index, len)
+            }
+            UnexpectedOverride => write!(f, "only a `mod` override can remove or merge keys"),
+            BadPath(path) => write!(f, "`${}` doesn't exist", path),
+            Private(path) => write!(f, "`${}` is private", path),
+            NotText(path) => write!(f, "`${}` can't be interpolated into a string", path),
+            UnknownField { class, field } => write!(f, "`{}` has no field `{}`", class, field),
+            MissingField { class, field } => {
+                write!(
+                    f,
+                    "`{}` is missing field `{}`, which has no default",
+                    class, field
+                )
+            }
+            OutOfRange { field, value, ty } => {
+                write!(
+                    f,
+                    "`{}` doesn't fit in `{}`, the type of `{}`",
+                    value, ty, field
+                )
+            }
+            Operands { op, found } => write!(f, "`{}` can't be applied to {}", op, found),
+            Overflow(op) => write!(f, "`{}` overflowed", op),
+            DivideByZero => write!(f, "division by zero"),
+            UnknownFunction(name) => write!(f, "there's no function `{}`", name),
+            Arity {
+                function,
+                expected,
+                variadic,
+                found,
+            } => write!(
+                f,
+                "`{}` takes {}{} arguments, not {}",
+                function,
+                if *variadic { "at least " } else { "" },
+                expected,
+                found
+            ),
+            Argument {
+                function,
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "argument {} of `{}` must be {}, not {}",
+                index, function, expected, found
+            ),
+            Call { function, message } => write!(f, "`{}`: {}", function, message),
+            NotIterable(kind) => write!(f, "can't iterate over {}", kind),
+            Condition(kind) => write!(f, "a condition must be a boolean, not {}", kind),
+            KeyType(kind) => write!(f, "a dict key must be a string, not {}", kind),
+            Limit { limit, max } => match limit {
+                self::Limit::Depth => write!(f, "evaluation nests more than {} deep", max),
+                self::Limit::Steps => write!(f, "evaluation takes more than {} steps", max),
+                self::Limit::Size => write!(f, "a value's size is over {}", max),
+                self::Limit::Calls => write!(f, "template calls nest more than {} deep", max),
+            },
+        }
+    }
+}
+
+impl Document<'static> {
+    /// Evaluates just the value at `path` in this document, as `Evaluator::get` does.
+    ///
+    /// The document is evaluated on its own, without loading its imports, so values that refer
+    /// to them fail with `EvalErrorKind::Unloaded` naming the `use`. Names a glob import would
+    /// bring in fail with `EvalErrorKind::UnknownName`. Load the document through a `Loader` to
+    /// evaluate those.
+    pub fn get(&self, path: &str) -> Result<Node<'static>, EvalError> {
+        const URL: &str = "memory://document";
+        let mut loader = Loader::new(MemoryResolver::new());
+        loader.insert(URL, self.clone());
+        Evaluator::new(&loader).get(URL, path)
+    }
+}
+
+/// Applies a `mod` override to `base` in place.
+///
+/// Each entry of the override is combined with the value it replaces using its `@strategy`,
+/// `Strategy::Deep` by default, and `-key` removes an entry. For lists and tuples each key is
+/// the index of the item to replace or remove.
+pub fn apply<'s>(base: &mut Node<'s>, patch: HashMap<String, Node<'s>>) -> Result<(), EvalError> {
+    match &mut base.ast {
+        Ast::Map(_, fields) => {
+            for (key, value) in patch {
+                let old = fields.remove(&key);
+                if let Some(new) = patch_value(old, value)? {
+                    fields.insert(key, new);
+                }
+            }
+        }
+        Ast::List(_, items) | Ast::Tuple(_, items) => {
+            // Removed items are left as `None` so that every index refers to the original list.
+            let mut slots: Vec<_> = items.drain(..).map(Some).collect();
+            for (key, value) in patch {
+                let index = key.parse::<usize>().map_err(|_| {
+                    EvalError::new(value.span, EvalErrorKind::InvalidIndex(key.clone()))
+                })?;
+                match slots.get_mut(index) {
+                    Some(slot @ Some(_)) => *slot = patch_value(slot.take(), value)?,
+                    _ => {
+                        let len = slots.len();
+                        return Err(EvalError::new(
+                            value.span,
+                            EvalErrorKind::IndexOutOfRange { index, len },
+                        ));
+                    }
+                }
+            }
+            items.extend(slots.into_iter().flatten());
+        }
+        _ => return Err(EvalError::new(base.span, EvalErrorKind::Shape)),
+    }
+    Ok(())
+}
+
+/// Combines an entry of a `mod` override with the value it replaces, returning `None` if the
+/// entry removes it.
+fn patch_value<'s>(old: Option<Node<'s>>, new: Node<'s>) -> Result<Option<Node<'s>>, EvalError> {
+    let (strategy, new) = match new.ast {
+        Ast::Remove => return Ok(None),
+        Ast::Merge(strategy, new) => (strategy, *new),
+        ast => (Strategy::Deep, Node::new(new.span, ast)),
+    };
+    match old {
+        Some(old) => merge(old, new, &strategy).map(Some),
+        None => finish(new).map(Some),
+    }
+}
+
+/// Turns an override that has nothing to merge into into a plain value.
+fn finish(node: Node) -> Result<Node, EvalError> {
+    match node.ast {
+        Ast::Map(None, patch) => {
+            let mut value = Node::new(node.span, Ast::Map(None, HashMap::new()));
+            apply(&mut value, patch)?;
+            Ok(value)
+        }
+        ast => Ok(Node::new(node.span, ast)),
+    }
+}
+
+/// Combines `base` with `other` using `strategy`.
+///
+/// `other` may be a `mod` override, with `-key` removals and `@strategy` entries of its own.
+pub fn merge<'s>(
+    base: Node<'s>,
+    other: Node<'s>,
+    strategy: &Strategy,
+) -> Result<Node<'s>, EvalError> {
+    let span = base.span;
+    match strategy {
+        Strategy::Replace => finish(other),
+        Strategy::Deep => match other.ast {
+            Ast::Map(None, patch) if mergeable(&base) => {
+                let mut base = base;
+                apply(&mut base, patch)?;
+                Ok(base)
+            }
+            ast => finish(Node::new(other.span, ast)),
+        },
+        Strategy::Append | Strategy::Prepend => match (base.ast, other.ast) {
+            (Ast::List(name, mut items), Ast::List(_, more)) => {
+                if *strategy == Strategy::Append {
+                    items.extend(more);
+                } else {
+                    items.splice(0..0, more);
+                }
+                Ok(Node::new(span, Ast::List(name, items)))
+            }
+            _ => Err(EvalError::new(other.span, EvalErrorKind::Shape)),
+        },
+        Strategy::MergeBy(key) => match (base.ast, other.ast) {
+            (Ast::List(name, mut items), Ast::List(_, more)) => {
+                for item in more {
+                    let found = identity(&item, key).and_then(|id| {
+                        items
+                            .iter()
+                            .position(|i| identity(i, key).as_ref() == Some(&id))
+                    });
+                    match found {
+                        Some(i) => {
+                            let old =
+                                std::mem::replace(&mut items[i], Node::new(span, Ast::Remove));
+                            items[i] = merge(old, item, &Strategy::Deep)?;
+                        }
+                        None => items.push(finish(item)?),
+                    }
+                }
+                Ok(Node::new(span, Ast::List(name, items)))
+            }
+            _ => Err(EvalError::new(other.span, EvalErrorKind::Shape)),
+        },
+    }
+}
+
+/// The value of the field `key` of a dict, as used by `Strategy::MergeBy` to match items.
+fn identity(node: &Node, key: &str) -> Option<String> {
+    let field = match &node.ast {
+        Ast::Map(_, fields) => fields.get(key)?,
+        _ => return None,
+    };
+    match &field.ast {
+        Ast::Literal(literal) => Some(literal.clone().into()),
+        Ast::Ident(name) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+fn mergeable(node: &Node) -> bool {
+    matches!(node.ast, Ast::Map(..) | Ast::List(..) | Ast::Tuple(..))
+}
+
+/// The entry `name` of a document's top-level dict.
+fn local<'m>(module: &'m Module, name: &str) -> Option<&'m Node<'static>> {
+    match &module.doc.root {
+        Some(Node {
+            ast: Ast::Map(None, fields),
+            ..
+        }) => fields.get(name),
+        _ => None,
+    }
+}
+
+/// Follows `path` into `value` by dict key or list index.
+fn walk(mut value: Node<'static>, path: &[String]) -> Option<Node<'static>> {
+    for key in path {
+        value = match value.ast {
+            Ast::Map(_, mut fields) => fields.remove(key)?,
+            Ast::List(_, mut items) | Ast::Tuple(_, mut items) => {
+                let i = key.parse::<usize>().ok().filter(|&i| i < items.len())?;
+                items.swap_remove(i)
+            }
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+/// Writes a literal as it's interpolated into a string: numbers without their width suffix.
+fn display(literal: Literal) -> String {
+    match literal {
+        Literal::Str(s) => s,
+        Literal::Bool(b) => b.to_string(),
+        Literal::Int(n) => Number::from(n).to_string(),
+        Literal::F32(f) => f.to_string(),
+        Literal::F64(f) => f.to_string(),
+        Literal::Selector(s) => s.to_string(),
+    }
+}
+
+/// Describes the kind of a value for error messages.
+fn kind(ast: &Ast) -> &'static str {
+    match ast {
+        Ast::Literal(Literal::Bool(_)) => "a boolean",
+        Ast::Literal(Literal::Str(_)) => "a string",
+        Ast::Literal(Literal::Selector(_)) => "a selector",
+        Ast::Literal(Literal::Int(_)) => "an integer",
+        Ast::Literal(_) => "a float",
+        Ast::Ident(_) => "a name",
+        Ast::List(..) => "a list",
+        Ast::Tuple(..) => "a tuple",
+        Ast::Map(..) => "a dict",
+        _ => "a value",
+    }
+}
+
+fn float(literal: &Literal) -> Option<f64> {
+    match literal {
+        Literal::Int(n) => Some(Number::from(n.clone()).to_f64()),
+        Literal::F32(f) => Some(f64::from(*f)),
+        Literal::F64(f) => Some(*f),
+        _ => None,
+    }
+}
+
+/// Orders two strings, booleans or numbers, or returns `None` if they can't be compared.
+///
+/// The inner `None` is for numbers that are unordered, like NaN.
+fn compare(a: &Literal, b: &Literal) -> Option<Option<Ordering>> {
+    use Literal::*;
+    match (a, b) {
+        (Str(a), Str(b)) => Some(Some(a.cmp(b))),
+        (Bool(a), Bool(b)) => Some(Some(a.cmp(b))),
+        (Int(a), Int(b)) if a.to_i128().is_some() && b.to_i128().is_some() => {
+            Some(Some(a.to_i128().cmp(&b.to_i128())))
+        }
+        _ => Some(float(a)?.partial_cmp(&float(b)?)),
+    }
+}
+
+fn arithmetic<T>(op: BinOp, a: T, b: T) -> T
+where
+    T: Add<Output = T> + Sub<Output = T> + Mul<Output = T> + Div<Output = T> + Rem<Output = T>,
+{
+    match op {
+        BinOp::Add => a + b,
+        BinOp::Sub => a - b,
+        BinOp::Mul => a * b,
+        BinOp::Div => a / b,
+        _ => a % b,
+    }
+}
+
+/// Applies a binary operator to two evaluated values.
+///
+/// Integers stay integers, sized to fit their result like a literal would be; any float makes
+/// the result an `f64`, unless both are `f32`. `+` also concatenates strings, and `==` and `!=`
+/// compare names as well as literals.
+fn binary<'s>(op: BinOp, left: &Ast<'s>, right: &Ast<'s>) -> Result<Ast<'s>, EvalErrorKind> {
+    use BinOp::*;
+    use Literal::*;
+    let operands = || EvalErrorKind::Operands {
+        op: op.as_str().into(),
+        found: format!("{} and {}", kind(left), kind(right)),
+    };
+    let (a, b) = match (left, right) {
+        (Ast::Literal(a), Ast::Literal(b)) => (a, b),
+        (Ast::Ident(a), Ast::Ident(b)) if matches!(op, Eq | Ne) => {
+            return Ok(Ast::Literal(Bool((a == b) == (op == Eq))));
+        }
+        _ => return Err(operands()),
+    };
+    let literal = match op {
+        And | Or => match (a, b) {
+            (Bool(a), Bool(b)) if op == And => Bool(*a && *b),
+            (Bool(a), Bool(b)) => Bool(*a || *b),
+            _ => return Err(operands()),
+        },
+        Eq | Ne | Lt | Le | Gt | Ge => {
+            let ordering = match (a, b) {
+                (Selector(_), Selector(_)) if matches!(op, Eq | Ne) => {
+                    Some(Ordering::Equal).filter(|_| a == b)
+                }
+                _ => compare(a, b).ok_or_else(operands)?,
+            };
+            Bool(match op {
+                Eq => ordering == Some(Ordering::Equal),
+                Ne => ordering != Some(Ordering::Equal),
+                Lt => ordering == Some(Ordering::Less),
+                Le => matches!(ordering, Some(Ordering::Less) | Some(Ordering::Equal)),
+                Gt => ordering == Some(Ordering::Greater),
+                _ => matches!(ordering, Some(Ordering::Greater) | Some(Ordering::Equal)),
+            })
+        }
+        _ => match (a, b) {
+            (Str(a), Str(b)) if op == Add => Str(format!("{}{}", a, b)),
+            (Int(a), Int(b)) => {
+                let overflow = || EvalErrorKind::Overflow(op.as_str().into());
+                let a = a.to_i128().ok_or_else(overflow)?;
+                let b = b.to_i128().ok_or_else(overflow)?;
+                if b == 0 && matches!(op, Div | Rem) {
+                    return Err(EvalErrorKind::DivideByZero);
+                }
+                let n = match op {
+                    Add => a.checked_add(b),
+                    Sub => a.checked_sub(b),
+                    Mul => a.checked_mul(b),
+                    Div => a.checked_div(b),
+                    _ => a.checked_rem(b),
+                };
+                Int(n.and_then(SizedNum::from_i128).ok_or_else(overflow)?)
+            }
+            (F32(a), F32(b)) => F32(arithmetic(op, *a, *b)),
+            _ => match (float(a), float(b)) {
+                (Some(a), Some(b)) => F64(arithmetic(op, a, b)),
+                _ => return Err(operands()),
+            },
+        },
+    };
+    Ok(Ast::Literal(literal))
+}
+
+fn unary<'s>(op: UnOp, value: &Ast<'s>) -> Result<Ast<'s>, EvalErrorKind> {
+    let literal = match (op, value) {
+        (UnOp::Not, Ast::Literal(Literal::Bool(b))) => Literal::Bool(!b),
+        (UnOp::Neg, Ast::Literal(Literal::Int(n))) => {
+            match n.to_i128().and_then(|n| SizedNum::from_i128(-n)) {
+                Some(n) => Literal::Int(n),
+                None => return Err(EvalErrorKind::Overflow(op.as_str().into())),
+            }
+        }
+        (UnOp::Neg, Ast::Literal(Literal::F32(f))) => Literal::F32(-f),
+        (UnOp::Neg, Ast::Literal(Literal::F64(f))) => Literal::F64(-f),
+        _ => {
+            return Err(EvalErrorKind::Operands {
+                op: op.as_str().into(),
+                found: kind(value).into(),
+            })
+        }
+    };
+    Ok(Ast::Literal(literal))
+}
+
+/// The size of a value, as bounded by `Limits::size`: the number of nodes in it, plus the length
+/// of each string and dict key. Stops counting once it's over `max`.
+fn size(node: &Node, max: usize) -> usize {
+    let mut total = 1;
+    match &node.ast {
+        Ast::Literal(Literal::Str(s)) => total += s.len(),
+        Ast::List(_, items) | Ast::Tuple(_, items) => {
+            for item in items {
+                if total > max {
+                    break;
+                }
+                total += size(item, max - total);
+            }
+        }
+        Ast::Map(_, fields) => {
+            for (key, value) in fields {
+                if total > max {
+                    break;
+                }
+                total += key.len() + size(value, max - total);
+            }
+        }
+        _ => (),
+    }
+    total
+}
+
+/// The name and arguments of a pipe stage or function argument: a bare name like `upper`, or a
+/// call missing its first argument like `replace("-", "_")`.
+fn callee<'a>(ast: &'a Ast<'static>) -> Option<(&'a str, &'a [Node<'static>])> {
+    match ast {
+        Ast::Ident(name) => Some((name, &[])),
+        ast => ast.call(),
+    }
+}
+
+/// Writes a reference as `name.path` for error messages.
+fn ref_name(name: &str, path: &[String]) -> String {
+    let mut full = name.to_string();
+    for key in path {
+        full.push('.');
+        full.push_str(key);
+    }
+    full
+}
+
+/// Evaluates the documents in a `Loader`, resolving names across their imports.
+///
+/// Names are evaluated on demand, each at most once: evaluating a value only evaluates the
+/// definitions it refers to, in whichever documents they're in.
+pub struct Evaluator<'l, R> {
+    loader: &'l Loader<R>,
+    /// The definitions being evaluated, as `(url, name)`.
+    stack: RefCell<Vec<(String, String)>>,
+    /// The value of each definition evaluated so far, by `(url, name)`.
+    cache: RefCell<HashMap<(String, String), Node<'static>>>,
+    /// The names bound by the comprehensions being evaluated, innermost last.
+    locals: RefCell<Vec<(String, Node<'static>)>>,
+    functions: Functions,
+    limits: Limits,
+    /// How many nodes have been evaluated, and how many are being evaluated now.
+    steps: Cell<usize>,
+    depth: Cell<usize>,
+    /// How many template calls are being evaluated now.
+    calls: Cell<usize>,
+}
+
+impl<'l, R: Resolver> Evaluator<'l, R> {
+    pub fn new(loader: &'l Loader<R>) -> Self {
+        Self {
+            loader,
+            stack: RefCell::new(Vec::new()),
+            cache: RefCell::new(HashMap::new()),
+            locals: RefCell::new(Vec::new()),
+            functions: Functions::builtins(),
+            limits: Limits::default(),
+            steps: Cell::new(0),
+            depth: Cell::new(0),
+            calls: Cell::new(0),
+        }
+    }
+
+    /// Bounds the work this evaluator will do from now on.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// The functions documents can call: the built-ins, unless they've been replaced.
+    pub fn functions_mut(&mut self) -> &mut Functions {
+        &mut self.functions
+    }
+
+    /// Registers a Rust function documents can call as `name`. See `Functions::register`.
+    pub fn register<Args, F: HostFunction<Args>>(&mut self, name: &str, f: F) {
+        self.functions.register(name, f);
+    }
+
+    fn module(&self, url: &str) -> Result<&'l Module, EvalError> {
+        self.loader.module(url).ok_or_else(|| {
+            EvalError::new(
+                Span::default(),
+                EvalErrorKind::UnknownModule(url.to_string()),
+            )
+        })
+    }
+
+    /// Evaluates the value of the document at `url`.
+    pub fn eval(&self, url: &str) -> Result<Option<Node<'static>>, EvalError> {
+        match &self.module(url)?.doc.root {
+            Some(Node {
+                span,
+                ast: Ast::Map(None, fields),
+            }) => {
+                // Go through `lookup` so each entry is only evaluated once, however many other
+                // entries refer to it.
+                let fields = fields
+                    .keys()
+                    .map(|name| Ok((name.clone(), self.lookup(url, name, *span)?)))
+                    .collect::<Result<_, EvalError>>()?;
+                Ok(Some(Node::new(*span, Ast::Map(None, fields))))
+            }
+            Some(root) => self.eval_node(url, root).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Evaluates just the value at `path` in the document at `url`: a name, as `$name` would
+    /// refer to it, followed by any fields or indexes, like `servers.0.port`.
+    pub fn get(&self, url: &str, path: &str) -> Result<Node<'static>, EvalError> {
+        let mut path = path.split('.').map(String::from);
+        let name = path.next().unwrap();
+        self.resolve(url, &name, &path.collect::<Vec<_>>(), Span::default())
+    }
+
+    /// Evaluates the value bound to `name` in the document at `url`: an entry of its top-level
+    /// dict, or a definition it imports.
+    pub fn lookup(&self, url: &str, name: &str, span: Span) -> Result<Node<'static>, EvalError> {
+        let module = self.module(url)?;
+        let node = match (local(module, name), module.names.get(name)) {
+            (Some(node), _) => node,
+            (None, Some((import, name))) => return self.lookup(import, name, span),
+            (None, None) => {
+                let kind = match module.unloaded(name) {
+                    Some(target) => EvalErrorKind::Unloaded(target.to_string()),
+                    None => EvalErrorKind::UnknownName(name.to_string()),
+                };
+                return Err(EvalError::new(span, kind).at(url));
+            }
+        };
+
+        let key = (url.to_string(), name.to_string());
+        if let Some(value) = self.cache.borrow().get(&key) {
+            return Ok(value.clone());
+        }
+        if let Some(i) = self.stack.borrow().iter().position(|k| *k == key) {
+            let names = self.stack.borrow()[i..]
+                .iter()
+                .chain(Some(&key))
+                .map(|(_, name)| name.clone())
+                .collect();
+            return Err(EvalError::new(span, EvalErrorKind::Cycle(names)).at(url));
+        }
+        self.stack.borrow_mut().push(key.clone());
+        // A definition can't see the names bound by a comprehension that refers to it.
+        let locals = self.locals.replace(Vec::new());
+        let value = self.eval_node(url, node);
+        self.locals.replace(locals);
+        self.stack.borrow_mut().pop();
+        if let Ok(value) = &value {
+            self.cache.borrow_mut().insert(key, value.clone());
+        }
+        value
+    }
+
+    /// Evaluates `$name.path`, as seen from the document at `url`.
+    ///
+    /// `name` may also be the alias of an imported document, in which case the path starts with
+    /// one of its public values.
+    pub fn resolve(
+        &self,
+        url: &str,
+        name: &str,
+        path: &[String],
+        span: Span,
+    ) -> Result<Node<'static>, EvalError> {
+        let module = self.module(url)?;
+        let error = |kind| Err(EvalError::new(span, kind).at(url));
+        let bound = local(module, name).is_some() || module.names.contains_key(name);
+        let (value, rest) = match (module.imports.get(name), path.split_first()) {
+            (Some(import), Some((first, rest))) if !bound => {
+                if !self.module(import)?.doc.is_public(first) {
+                    return error(EvalErrorKind::Private(ref_name(name, path)));
+                }
+                (self.lookup(import, first, span)?, rest)
+            }
+            _ => (self.lookup(url, name, span)?, path),
+        };
+        match walk(value, rest) {
+            Some(value) => Ok(value),
+            None => error(EvalErrorKind::BadPath(ref_name(name, path))),
+        }
+    }
+
+    /// Evaluates `node` within the evaluator's limits.
+    fn eval_node(&self, url: &str, node: &Node<'static>) -> Result<Node<'static>, EvalError> {
+        let error = |limit, max| {
+            let kind = EvalErrorKind::Limit { limit, max };
+            Err(EvalError::new(node.span, kind).at(url))
+        };
+        self.steps.set(self.steps.get() + 1);
+        match self.limits.steps {
+            Some(max) if self.steps.get() > max => return error(Limit::Steps, max),
+            _ => (),
+        }
+        match self.limits.depth {
+            Some(max) if self.depth.get() >= max => return error(Limit::Depth, max),
+            _ => (),
+        }
+        self.depth.set(self.depth.get() + 1);
+        let value = self.eval_ast(url, node);
+        self.depth.set(self.depth.get() - 1);
+        let value = value?;
+        match self.limits.size {
+            Some(max) if size(&value, max) > max => error(Limit::Size, max),
+            _ => Ok(value),
+        }
+    }
+
+    fn eval_ast(&self, url: &str, node: &Node<'static>) -> Result<Node<'static>, EvalError> {
+        // Each arm that needs more than a line or two has its own method, and the arms share one
+        // `?`, so a deeply recursive template only costs the stack of the arms it goes through.
+        let ast = match &node.ast {
+            Ast::Ref(name, path) => return self.eval_ref(url, node, name, path),
+            Ast::Ident(name) => match self.local(name) {
+                Some(mut value) => {
+                    value.span = node.span;
+                    return Ok(value);
+                }
+                None => Ok(Ast::Ident(name.clone())),
+            },
+            Ast::If(cond, then, otherwise) => return self.eval_if(url, cond, then, otherwise),
+            Ast::ListComp(item, comp) => self.eval_list_comp(url, item, comp),
+            Ast::DictComp(key, value, comp) => self.eval_dict_comp(url, key, value, comp),
+            Ast::Interpolate(parts) => self.interpolate(url, parts),
+            Ast::Mod(base, patch) => return self.eval_mod(url, node, base, patch),
+            Ast::Map(Some(class), fields) => self.eval_instance(url, node, class, fields),
+            Ast::Map(None, fields) => self
+                .eval_fields(url, fields)
+                .map(|fields| Ast::Map(None, fields)),
+            Ast::List(name, items) => self
+                .eval_items(url, items)
+                .map(|items| Ast::List(name.clone(), items)),
+            Ast::Tuple(Some(name), args) if node.ast.call().is_some() => {
+                return self.eval_call(url, node, name, args)
+            }
+            Ast::Tuple(name, items) => self
+                .eval_items(url, items)
+                .map(|items| Ast::Tuple(name.clone(), items)),
+            Ast::Binary(op, left, right) => self.eval_binary(url, node, *op, left, right),
+            Ast::Unary(op, inner) => self.eval_node(url, inner).and_then(|value| {
+                unary(*op, &value.ast).map_err(|kind| EvalError::new(node.span, kind).at(url))
+            }),
+            Ast::Pipe(value, f) => self.eval_pipe(url, value, f),
+            Ast::Remove | Ast::Merge(..) => {
+                Err(EvalError::new(node.span, EvalErrorKind::UnexpectedOverride).at(url))
+            }
+            ast => Ok(ast.clone()),
+        }?;
+        Ok(Node::new(node.span, ast))
+    }
+
+    fn eval_if(
+        &self,
+        url: &str,
+        cond: &Node<'static>,
+        then: &Node<'static>,
+        otherwise: &Node<'static>,
+    ) -> Result<Node<'static>, EvalError> {
+        let branch = if self.condition(url, cond)? {
+            then
+        } else {
+            otherwise
+        };
+        self.eval_node(url, branch)
+    }
+
+    fn eval_ref(
+        &self,
+        url: &str,
+        node: &Node<'static>,
+        name: &str,
+        path: &[String],
+    ) -> Result<Node<'static>, EvalError> {
+        let mut value = match self.local(name) {
+            Some(value) => walk(value, path).ok_or_else(|| {
+                let kind = EvalErrorKind::BadPath(ref_name(name, path));
+                EvalError::new(node.span, kind).at(url)
+            })?,
+            None => self.resolve(url, name, path, node.span)?,
+        };
+        value.span = node.span;
+        Ok(value)
+    }
+
+    fn eval_list_comp(
+        &self,
+        url: &str,
+        item: &Node<'static>,
+        comp: &Comprehension<'static>,
+    ) -> Result<Ast<'static>, EvalError> {
+        let mut items = Vec::new();
+        self.comprehend(url, comp, || {
+            items.push(self.eval_node(url, item)?);
+            Ok(())
+        })?;
+        Ok(Ast::List(None, items))
+    }
+
+    fn eval_dict_comp(
+        &self,
+        url: &str,
+        key: &Node<'static>,
+        value: &Node<'static>,
+        comp: &Comprehension<'static>,
+    ) -> Result<Ast<'static>, EvalError> {
+        let mut fields = HashMap::new();
+        self.comprehend(url, comp, || {
+            let name = match self.eval_node(url, key)?.ast {
+                Ast::Ident(name) => name,
+                Ast::Literal(literal @ Literal::Str(_))
+                | Ast::Literal(literal @ Literal::Int(_)) => display(literal),
+                other => {
+                    let kind = EvalErrorKind::KeyType(kind(&other).into());
+                    return Err(EvalError::new(key.span, kind).at(url));
+                }
+            };
+            fields.insert(name, self.eval_node(url, value)?);
+            Ok(())
+        })?;
+        Ok(Ast::Map(None, fields))
+    }
+
+    fn interpolate(&self, url: &str, parts: &[Node<'static>]) -> Result<Ast<'static>, EvalError> {
+        let mut text = String::new();
+        for part in parts {
+            match (&part.ast, self.eval_node(url, part)?.ast) {
+                (_, Ast::Literal(literal)) => text.push_str(&display(literal)),
+                (Ast::Ref(name, path), _) => {
+                    let kind = EvalErrorKind::NotText(ref_name(name, path));
+                    return Err(EvalError::new(part.span, kind).at(url));
+                }
+                _ => unreachable!(),
+            }
+        }
+        Ok(Ast::Literal(Literal::Str(text)))
+    }
+
+    fn eval_mod(
+        &self,
+        url: &str,
+        node: &Node<'static>,
+        base: &str,
+        patch: &HashMap<String, Node<'static>>,
+    ) -> Result<Node<'static>, EvalError> {
+        let mut value = self.lookup(url, base, node.span)?;
+        apply(&mut value, self.eval_patch(url, patch)?).map_err(|e| e.at(url))?;
+        value.span = node.span;
+        Ok(value)
+    }
+
+    fn eval_instance(
+        &self,
+        url: &str,
+        node: &Node<'static>,
+        class: &str,
+        fields: &HashMap<String, Node<'static>>,
+    ) -> Result<Ast<'static>, EvalError> {
+        let fields = match self.instantiate(url, class, fields, node.span)? {
+            Some(fields) => fields,
+            None => self.eval_fields(url, fields)?,
+        };
+        Ok(Ast::Map(Some(class.to_string()), fields))
+    }
+
+    /// Calls the template or function `name`.
+    fn eval_call(
+        &self,
+        url: &str,
+        node: &Node<'static>,
+        name: &str,
+        args: &[Node<'static>],
+    ) -> Result<Node<'static>, EvalError> {
+        match self.template(url, name) {
+            Some((module, def)) => {
+                let args = self.eval_items(url, args)?;
+                self.call_template(url, module, def, args, node.span)
+            }
+            None => self.call_function(url, node, name, args),
+        }
+    }
+
+    fn call_function(
+        &self,
+        url: &str,
+        node: &Node<'static>,
+        name: &str,
+        args: &[Node<'static>],
+    ) -> Result<Node<'static>, EvalError> {
+        let args = self.eval_args(url, name, args, 0)?;
+        let ast = self
+            .functions
+            .call(name, args)
+            .map_err(|kind| EvalError::new(node.span, kind).at(url))?;
+        Ok(Node::new(node.span, ast))
+    }
+
+    fn eval_binary(
+        &self,
+        url: &str,
+        node: &Node<'static>,
+        op: BinOp,
+        left: &Node<'static>,
+        right: &Node<'static>,
+    ) -> Result<Ast<'static>, EvalError> {
+        let left = self.eval_node(url, left)?;
+        // `&&` and `||` only evaluate their right side if they need to.
+        let decided = match (op, &left.ast) {
+            (BinOp::And, Ast::Literal(Literal::Bool(b))) => !b,
+            (BinOp::Or, Ast::Literal(Literal::Bool(b))) => *b,
+            _ => false,
+        };
+        if decided {
+            return Ok(left.ast);
+        }
+        let right = self.eval_node(url, right)?;
+        binary(op, &left.ast, &right.ast).map_err(|kind| EvalError::new(node.span, kind).at(url))
+    }
+
+    fn eval_pipe(
+        &self,
+        url: &str,
+        value: &Node<'static>,
+        f: &Node<'static>,
+    ) -> Result<Ast<'static>, EvalError> {
+        let value = self.eval_node(url, value)?;
+        // A template is called with the value before any arguments it's given.
+        let template =
+            callee(&f.ast).and_then(|(name, args)| Some((self.template(url, name)?, args)));
+        if let Some(((module, def), args)) = template {
+            let mut items = vec![value];
+            items.extend(self.eval_items(url, args)?);
+            return self
+                .call_template(url, module, def, items, f.span)
+                .map(|node| node.ast);
+        }
+        let f = match f.ast.call() {
+            Some((name, args)) => {
+                let args = self.eval_args(url, name, args, 1)?;
+                Node::new(f.span, Ast::Tuple(Some(name.to_string()), args))
+            }
+            None => f.clone(),
+        };
+        self.functions
+            .apply(&f, value)
+            .map_err(|kind| EvalError::new(f.span, kind).at(url))
+    }
+
+    /// The value bound to `name` by the innermost comprehension that binds it.
+    fn local(&self, name: &str) -> Option<Node<'static>> {
+        let locals = self.locals.borrow();
+        let (_, value) = locals.iter().rev().find(|(n, _)| n == name)?;
+        Some(value.clone())
+    }
+
+    fn condition(&self, url: &str, node: &Node<'static>) -> Result<bool, EvalError> {
+        match self.eval_node(url, node)?.ast {
+            Ast::Literal(Literal::Bool(b)) => Ok(b),
+            other => {
+                let kind = EvalErrorKind::Condition(kind(&other).into());
+                Err(EvalError::new(node.span, kind).at(url))
+            }
+        }
+    }
+
+    /// Calls `f` for each item of a comprehension that passes its filter, with the item's names
+    /// bound for it to use.
+    fn comprehend(
+        &self,
+        url: &str,
+        comp: &Comprehension<'static>,
+        mut f: impl FnMut() -> Result<(), EvalError>,
+    ) -> Result<(), EvalError> {
+        let items = self.eval_node(url, &comp.items)?;
+        // Each item as (index or key, item or value).
+        let (entries, dict): (Vec<_>, _) = match items.ast {
+            Ast::List(_, items) | Ast::Tuple(_, items) => {
+                let entries = items.into_iter().enumerate().map(|(i, item)| {
+                    let index = SizedNum::from_i128(i as i128).unwrap();
+                    (
+                        Node::new(item.span, Ast::Literal(Literal::Int(index))),
+                        item,
+                    )
+                });
+                (entries.collect(), false)
+            }
+            Ast::Map(_, fields) => {
+                let mut fields: Vec<_> = fields.into_iter().collect();
+                fields.sort_by(|a, b| a.0.cmp(&b.0));
+                let entries = fields.into_iter().map(|(key, value)| {
+                    (
+                        Node::new(value.span, Ast::Literal(Literal::Str(key))),
+                        value,
+                    )
+                });
+                (entries.collect(), true)
+            }
+            other => {
+                let kind = EvalErrorKind::NotIterable(kind(&other).into());
+                return Err(EvalError::new(comp.items.span, kind).at(url));
+            }
+        };
+        let depth = self.locals.borrow().len();
+        let run = || {
+            for (key, value) in entries {
+                // One name binds the item of a list, or the key of a dict.
+                let values = match (comp.vars.len(), dict) {
+                    (1, false) => vec![value],
+                    (1, true) => vec![key],
+                    _ => vec![key, value],
+                };
+                let mut locals = self.locals.borrow_mut();
+                locals.truncate(depth);
+                locals.extend(comp.vars.iter().cloned().zip(values));
+                drop(locals);
+                let keep = match &comp.filter {
+                    Some(filter) => self.condition(url, filter)?,
+                    None => true,
+                };
+                if keep {
+                    f()?;
+                }
+            }
+            Ok(())
+        };
+        let result = run();
+        self.locals.borrow_mut().truncate(depth);
+        result
+    }
+
+    /// Finds the document that declares the class or enum `name`, as seen from the document at
+    /// `url`, and the name it's declared with there.
+    fn declaration(&self, url: &str, name: &str) -> Option<(&'l Module, String)> {
+        declaration(self.loader, url, name)
+    }
+
+    /// Finds the template `name`, as seen from the document at `url`, and the document that
+    /// declares it.
+    fn template(&self, url: &str, name: &str) -> Option<(&'l Module, &'l TemplateDef<'static>)> {
+        let module = self.loader.module(url)?;
+        if let Some(def) = module.doc.template(name) {
+            return Some((module, def));
+        }
+        let (import, name) = module.names.get(name)?;
+        self.template(import, name)
+    }
+
+    /// Calls the template `def` from the document at `url`. Its body is evaluated in `module`,
+    /// the document that declares it, with each parameter bound to its argument or default.
+    fn call_template(
+        &self,
+        url: &str,
+        module: &'l Module,
+        def: &'l TemplateDef<'static>,
+        args: Vec<Node<'static>>,
+        span: Span,
+    ) -> Result<Node<'static>, EvalError> {
+        self.check_call(url, module, def, &args, span)?;
+        // The body only sees its parameters, not the names bound where it's called from.
+        let saved = self.locals.replace(Vec::new());
+        self.calls.set(self.calls.get() + 1);
+        let value = self
+            .bind_params(module, def, args)
+            .and_then(|()| self.eval_node(&module.url, &def.body));
+        self.calls.set(self.calls.get() - 1);
+        self.locals.replace(saved);
+        value.map(|mut value| {
+            value.span = span;
+            value
+        })
+    }
+
+    /// Binds each parameter of the template `def` to its argument or default.
+    fn bind_params(
+        &self,
+        module: &'l Module,
+        def: &TemplateDef<'static>,
+        args: Vec<Node<'static>>,
+    ) -> Result<(), EvalError> {
+        let mut args = args.into_iter();
+        for param in &def.params {
+            // Defaults are evaluated in order, so they can refer to earlier parameters.
+            let mut value = match (args.next(), &param.default) {
+                (Some(arg), _) => arg,
+                (None, Some(default)) => self.eval_node(&module.url, default)?,
+                (None, None) => unreachable!(),
+            };
+            self.resize(module, &param.name, &mut value, &param.ty)
+                .map_err(|kind| EvalError::new(value.span, kind).at(&module.url))?;
+            self.locals.borrow_mut().push((param.name.clone(), value));
+        }
+        Ok(())
+    }
+
+    /// Resizes the integers in `value` to the widths `ty` declares for them, as the checker does
+    /// for literals, where `ty` is the type of the field or parameter `field` in `module`.
+    fn resize(
+        &self,
+        module: &'l Module,
+        field: &str,
+        value: &mut Node<'static>,
+        ty: &StaticType,
+    ) -> Result<(), EvalErrorKind> {
+        let mut checker = Checker::in_module(self.loader, module);
+        match checker.resize(value, ty) {
+            Err(CheckError {
+                kind: CheckErrorKind::OutOfRange { value, ty },
+                ..
+            }) => Err(EvalErrorKind::OutOfRange {
+                field: field.to_string(),
+                value,
+                ty: ty.into(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that the template `def` can be called with `args`: that they fit its parameters,
+    /// and that calls aren't already nested as deeply as the limits allow.
+    fn check_call(
+        &self,
+        url: &str,
+        module: &'l Module,
+        def: &TemplateDef<'static>,
+        args: &[Node<'static>],
+        span: Span,
+    ) -> Result<(), EvalError> {
+        let error = |kind| Err(EvalError::new(span, kind).at(url));
+        match self.limits.calls {
+            Some(max) if self.calls.get() >= max => {
+                return error(EvalErrorKind::Limit {
+                    limit: Limit::Calls,
+                    max,
+                })
+            }
+            _ => (),
+        }
+        if args.len() < def.required() || args.len() > def.params.len() {
+            return error(EvalErrorKind::Arity {
+                function: def.name.clone(),
+                expected: if args.len() < def.required() {
+                    def.required()
+                } else {
+                    def.params.len()
+                },
+                variadic: false,
+                found: args.len(),
+            });
+        }
+        for (i, (param, arg)) in def.params.iter().zip(args).enumerate() {
+            let mut checker = Checker::in_module(self.loader, module);
+            checker.check(arg, &param.ty);
+            if checker.finish().is_err() {
+                return error(EvalErrorKind::Argument {
+                    function: def.name.clone(),
+                    index: i + 1,
+                    expected: param.ty.clone().into(),
+                    found: kind(&arg.ast).into(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The fields of the class `name` as seen from the document at `url`, including those it
+    /// inherits, each with the document that declares it.
+    ///
+    /// `seen` holds the classes already visited, so inheritance cycles end.
+    fn class_fields(
+        &self,
+        url: &str,
+        name: &str,
+        seen: &mut Vec<(String, String)>,
+    ) -> Option<Vec<(&'l Module, &'l FieldDef<'static>)>> {
+        let (module, name) = self.declaration(url, name)?;
+        let key = (module.url.clone(), name.clone());
+        if seen.contains(&key) {
+            return None;
+        }
+        seen.push(key);
+        let def = module.doc.class(&name).filter(|def| def.alias.is_none())?;
+        let mut fields = match &def.parent {
+            Some(parent) => self
+                .class_fields(&module.url, parent, seen)
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        for field in &def.fields {
+            match fields.iter_mut().find(|(_, f)| f.name == field.name) {
+                Some(inherited) => *inherited = (module, field),
+                None => fields.push((module, field)),
+            }
+        }
+        Some(fields)
+    }
+
+    /// The fields of a struct-like variant of the enum `ty`.
+    fn variant_fields(
+        &self,
+        url: &str,
+        ty: &str,
+        variant: &str,
+    ) -> Option<Vec<(&'l Module, &'l FieldDef<'static>)>> {
+        let (module, ty) = self.declaration(url, ty)?;
+        let def = module
+            .doc
+            .variants(&ty)?
+            .iter()
+            .find(|v| v.name == variant)?;
+        if def.is_tuple() {
+            return None;
+        }
+        Some(def.fields.iter().map(|f| (module, f)).collect())
+    }
+
+    /// Fills in the fields an instance of `class` leaves out from their defaults, returning
+    /// `None` if `class` isn't a declared class or enum variant with fields.
+    ///
+    /// Defaults are evaluated in the document that declares them.
+    pub fn instantiate(
+        &self,
+        url: &str,
+        class: &str,
+        fields: &HashMap<String, Node<'static>>,
+        span: Span,
+    ) -> Result<Option<HashMap<String, Node<'static>>>, EvalError> {
+        let defs = match split_variant(class) {
+            (Some(ty), variant) => self.variant_fields(url, ty, variant),
+            (None, _) => self.class_fields(url, class, &mut Vec::new()),
+        };
+        let defs = match defs {
+            Some(defs) => defs,
+            None => return Ok(None),
+        };
+        let error = |kind| Err(EvalError::new(span, kind).at(url));
+        if let Some(key) = fields
+            .keys()
+            .find(|k| !defs.iter().any(|(_, f)| f.name == **k))
+        {
+            return error(EvalErrorKind::UnknownField {
+                class: class.to_string(),
+                field: key.clone(),
+            });
+        }
+        let mut values = self.eval_fields(url, fields)?;
+        for (module, def) in defs {
+            if !values.contains_key(&def.name) {
+                match &def.default {
+                    Some(default) => {
+                        let value = self.eval_node(&module.url, default)?;
+                        values.insert(def.name.clone(), value);
+                    }
+                    None => {
+                        return error(EvalErrorKind::MissingField {
+                            class: class.to_string(),
+                            field: def.name.clone(),
+                        })
+                    }
+                }
+            }
+            let value = values.get_mut(&def.name).unwrap();
+            if let Err(kind) = self.resize(module, &def.name, value, &def.ty) {
+                return error(kind);
+            }
+        }
+        Ok(Some(values))
+    }
+
+    fn eval_items(
+        &self,
+        url: &str,
+        items: &[Node<'static>],
+    ) -> Result<Vec<Node<'static>>, EvalError> {
+        items.iter().map(|item| self.eval_node(url, item)).collect()
+    }
+
+    /// Evaluates the arguments to the function `name`, starting from the one at `offset`.
+    ///
+    /// A call passed where the function takes a `Param::Function` isn't made; only its own
+    /// arguments are evaluated, leaving room for the value it'll be applied to.
+    fn eval_args(
+        &self,
+        url: &str,
+        name: &str,
+        args: &[Node<'static>],
+        offset: usize,
+    ) -> Result<Vec<Node<'static>>, EvalError> {
+        let mut evaluated = Vec::with_capacity(args.len());
+        for (i, arg) in args.iter().enumerate() {
+            let param = self.functions.param(name, offset + i);
+            // Functions can only call other functions, so a template can't be passed to one.
+            let template = callee(&arg.ast).filter(|(f, _)| self.template(url, f).is_some());
+            if let (Some(Param::Function), Some(_)) = (param, template) {
+                return Err(EvalError::new(
+                    arg.span,
+                    EvalErrorKind::Argument {
+                        function: name.to_string(),
+                        index: offset + i + 1,
+                        expected: Param::Function.describe().to_string(),
+                        found: "a template".to_string(),
+                    },
+                )
+                .at(url));
+            }
+            evaluated.push(match (param, arg.ast.call()) {
+                (Some(Param::Function), Some((f, inner))) => {
+                    let inner = self.eval_args(url, f, inner, 1)?;
+                    Node::new(arg.span, Ast::Tuple(Some(f.to_string()), inner))
+                }
+                _ => self.eval_node(url, arg)?,
+            });
+        }
+        Ok(evaluated)
+    }
+
+    fn eval_fields(
+        &self,
+        url: &str,
+        fields: &HashMap<String, Node<'static>>,
+    ) -> Result<HashMap<String, Node<'static>>, EvalError> {
+        fields
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), self.eval_node(url, value)?)))
+            .collect()
+    }
+
+    /// Evaluates an override, keeping its `-key` removals.
+    fn eval_patch(
+        &self,
+        url: &str,
+        patch: &HashMap<String, Node<'static>>,
+    ) -> Result<HashMap<String, Node<'static>>, EvalError> {
+        patch
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), self.eval_patch_value(url, value)?)))
+            .collect()
+    }
+
+    fn eval_patch_value(
+        &self,
+        url: &str,
+        value: &Node<'static>,
+    ) -> Result<Node<'static>, EvalError> {
+        let ast = match &value.ast {
+            Ast::Remove => Ast::Remove,
+            Ast::Merge(strategy, inner) => Ast::Merge(
+                strategy.clone(),
+                Box::new(self.eval_patch_value(url, inner)?),
+            ),
+            Ast::Map(None, sub) => Ast::Map(None, self.eval_patch(url, sub)?),
+            _ => return self.eval_node(url, value),
+        };
+        Ok(Node::new(value.span, ast))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolve::MemoryResolver;
+
+    fn field<'a>(node: &'a Node<'static>, key: &str) -> &'a Ast<'static> {
+        match &node.ast {
+            Ast::Map(_, fields) => &fields[key].ast,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn mods() {
+        let files: MemoryResolver = vec![
+            (
+                "base.stn",
+                r#"pub server: { host: "localhost", port: 80, debug: true, tags: ["a", "b", "c"] }"#,
+            ),
+            (
+                "prod.stn",
+                r#"use base.stn::{server};
+                {
+                    local: { a: 1, b: { c: 2 } },
+                    patched: local < { b: { d: 3 }, -a },
+                    prod: server < { host: "example.com", -debug, tags: { 0: "x", -1 } },
+                    bad: server < { tags: { 3: "y" } },
+                    loop: loop < {},
+                }"#,
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files);
+        let url = loader.load("prod.stn").unwrap();
+        let eval = Evaluator::new(&loader);
+
+        let patched = eval.lookup(&url, "patched", Span::default()).unwrap();
+        match field(&patched, "b") {
+            Ast::Map(None, fields) => assert_eq!(fields.len(), 2),
+            other => panic!("{:?}", other),
+        }
+        match &patched.ast {
+            Ast::Map(_, fields) => assert!(!fields.contains_key("a")),
+            other => panic!("{:?}", other),
+        }
+
+        let prod = eval.lookup(&url, "prod", Span::default()).unwrap();
+        match field(&prod, "host") {
+            Ast::Literal(Literal::Str(host)) => assert_eq!(host, "example.com"),
+            other => panic!("{:?}", other),
+        }
+        match field(&prod, "tags") {
+            Ast::List(_, tags) => match &tags[..] {
+                [Node {
+                    ast: Ast::Literal(Literal::Str(a)),
+                    ..
+                }, Node {
+                    ast: Ast::Literal(Literal::Str(c)),
+                    ..
+                }] => assert_eq!((a.as_str(), c.as_str()), ("x", "c")),
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+
+        let err = eval.lookup(&url, "bad", Span::default()).unwrap_err();
+        assert_eq!(
+            err.kind,
+            EvalErrorKind::IndexOutOfRange { index: 3, len: 3 }
+        );
+        let err = eval.lookup(&url, "loop", Span::default()).unwrap_err();
+        assert_eq!(
+            err.kind,
+            EvalErrorKind::Cycle(vec!["loop".into(), "loop".into()])
+        );
+        assert_eq!(
+            eval.lookup(&url, "missing", Span::default())
+                .unwrap_err()
+                .kind,
+            EvalErrorKind::UnknownName("missing".into())
+        );
+    }
+
+    #[test]
+    fn strategies() {
+        let mut files = MemoryResolver::new();
+        files.insert(
+            "main.stn",
+            r#"{
+                base: {
+                    tags: ["b"],
+                    users: [{ id: 1, name: "a" }, { id: 2, name: "b" }],
+                    limits: { cpu: 1, mem: 2 },
+                },
+                patched: base < {
+                    tags @prepend: ["a"],
+                    users @merge: [{ id: 2, name: "c" }, { id: 3, name: "d" }],
+                    limits @replace: { cpu: 4 },
+                },
+                bad: base < { limits @append: [1] },
+            }"#,
+        );
+        let mut loader = Loader::new(files);
+        let url = loader.load("main.stn").unwrap();
+        let eval = Evaluator::new(&loader);
+        let patched = eval.lookup(&url, "patched", Span::default()).unwrap();
+        let strs = |ast: &Ast, key: &str| match ast {
+            Ast::List(_, items) => items
+                .iter()
+                .map(|item| match &item.ast {
+                    Ast::Literal(Literal::Str(s)) => s.clone(),
+                    Ast::Map(_, fields) => match &fields[key].ast {
+                        Ast::Literal(Literal::Str(s)) => s.clone(),
+                        other => panic!("{:?}", other),
+                    },
+                    other => panic!("{:?}", other),
+                })
+                .collect::<Vec<_>>(),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(strs(field(&patched, "tags"), ""), vec!["a", "b"]);
+        assert_eq!(strs(field(&patched, "users"), "name"), vec!["a", "c", "d"]);
+        match field(&patched, "limits") {
+            Ast::Map(None, fields) => assert_eq!(fields.len(), 1),
+            other => panic!("{:?}", other),
+        }
+        let err = eval.lookup(&url, "bad", Span::default()).unwrap_err();
+        assert_eq!(err.kind, EvalErrorKind::Shape);
+
+        let list = |items: &[&str]| {
+            let items = items
+                .iter()
+                .map(|s| Node::new(Span::default(), Ast::Literal(Literal::Str(s.to_string()))))
+                .collect();
+            Node::new(Span::default(), Ast::List(None, items))
+        };
+        let merged = merge(list(&["a"]), list(&["b"]), &Strategy::Append).unwrap();
+        assert_eq!(strs(&merged.ast, ""), vec!["a", "b"]);
+        let merged = merge(list(&["a"]), list(&["b"]), &Strategy::Replace).unwrap();
+        assert_eq!(strs(&merged.ast, ""), vec!["b"]);
+    }
+
+    #[test]
+    fn instances() {
+        let files: MemoryResolver = vec![
+            (
+                "ui.stn",
+                r#"pub class Button { label: String, width: u16 = 80, style: Map<String, bool> = { bold: false } }"#,
+            ),
+            (
+                "main.stn",
+                r#"use ui.stn::{Button};
+                class Toggle : super Button { width: u16 = 40, on: bool = false }
+                enum Shape { Rect { w: f32 = 1.0, h: f32 } }
+                {
+                    ok: Button { label: "OK" },
+                    dark: Toggle { label: "Dark", on: true },
+                    rect: Shape::Rect { h: 2.0 },
+                    extra: Button { label: "x", colour: "red" },
+                    missing: Toggle { on: false },
+                }"#,
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files);
+        let url = loader.load("main.stn").unwrap();
+        let eval = Evaluator::new(&loader);
+        let lookup = |name| eval.lookup(&url, name, Span::default());
+        let keys = |node: &Node| match &node.ast {
+            Ast::Map(_, fields) => {
+                let mut keys: Vec<_> = fields.keys().cloned().collect();
+                keys.sort();
+                keys
+            }
+            other => panic!("{:?}", other),
+        };
+
+        let ok = lookup("ok").unwrap();
+        assert_eq!(keys(&ok), vec!["label", "style", "width"]);
+        let dark = lookup("dark").unwrap();
+        assert_eq!(keys(&dark), vec!["label", "on", "style", "width"]);
+        match field(&dark, "width") {
+            Ast::Literal(Literal::Int(n)) => assert_eq!(n.magnitude().len(), 6),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(keys(&lookup("rect").unwrap()), vec!["h", "w"]);
+        assert_eq!(
+            lookup("extra").unwrap_err().kind,
+            EvalErrorKind::UnknownField {
+                class: "Button".into(),
+                field: "colour".into()
+            }
+        );
+        assert_eq!(
+            lookup("missing").unwrap_err().kind,
+            EvalErrorKind::MissingField {
+                class: "Toggle".into(),
+                field: "label".into()
+            }
+        );
+    }
+
+    #[test]
+    fn references() {
+        let files: MemoryResolver = vec![
+            (
+                "colors.stn",
+                r##"pub palette: { red: "#f00", greys: ["#111", "#222"] }"##,
+            ),
+            (
+                "main.stn",
+                r#"use colors.stn;
+                {
+                    host: "example.com",
+                    port: 8080,
+                    url: "https://${host}:${port}/",
+                    red: $colors.palette.red,
+                    grey: $colors.palette.greys.1,
+                    hex: "${red}",
+                    a: $b,
+                    b: { c: $a },
+                    missing: $colors.palette.blue,
+                    list: "${colors.palette.greys}",
+                }"#,
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files);
+        let url = loader.load("main.stn").unwrap();
+        let eval = Evaluator::new(&loader);
+        let lookup = |name| eval.lookup(&url, name, Span::default());
+        let text = |node: Node| match node.ast {
+            Ast::Literal(Literal::Str(s)) => s,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(text(lookup("url").unwrap()), "https://example.com:8080/");
+        assert_eq!(text(lookup("red").unwrap()), "#f00");
+        assert_eq!(text(lookup("grey").unwrap()), "#222");
+        assert_eq!(text(lookup("hex").unwrap()), "#f00");
+        assert_eq!(
+            lookup("a").unwrap_err().kind,
+            EvalErrorKind::Cycle(vec!["a".into(), "b".into(), "a".into()])
+        );
+        assert_eq!(
+            lookup("missing").unwrap_err().kind,
+            EvalErrorKind::BadPath("colors.palette.blue".into())
+        );
+        assert_eq!(
+            lookup("list").unwrap_err().kind,
+            EvalErrorKind::NotText("colors.palette.greys".into())
+        );
+    }
+
+    #[test]
+    fn expressions() {
+        let files: MemoryResolver = vec![(
+            "main.stn",
+            r#"{
+                base: 8080,
+                double: $base * 2,
+                offset: 10 - $base,
+                ratio: $base / 3 + 0.5,
+                big: 170141183460469231731687303715884105727 + 1,
+                name: "api" + "-" + "v2",
+                ok: $base >= 8000 && !($name == "api"),
+                lazy: false && $missing,
+                kind: Light == Light,
+                mixed: "a" * 2,
+                zero: 1 % (2 - 2),
+            }"#,
+        )]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files);
+        let url = loader.load("main.stn").unwrap();
+        let eval = Evaluator::new(&loader);
+        let lookup = |name| eval.lookup(&url, name, Span::default());
+        let literal = |name| match lookup(name).unwrap().ast {
+            Ast::Literal(literal) => literal,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(literal("double"), Literal::Int(SizedNum::from(16160u16)));
+        assert_eq!(literal("offset"), Literal::Int(SizedNum::from(-8070i16)));
+        assert_eq!(literal("ratio"), Literal::F64(2693.5));
+        assert_eq!(literal("name"), Literal::Str("api-v2".into()));
+        assert_eq!(literal("ok"), Literal::Bool(true));
+        assert_eq!(literal("lazy"), Literal::Bool(false));
+        assert_eq!(literal("kind"), Literal::Bool(true));
+        assert_eq!(
+            lookup("big").unwrap_err().kind,
+            EvalErrorKind::Overflow("+".into())
+        );
+        assert_eq!(
+            lookup("mixed").unwrap_err().kind,
+            EvalErrorKind::Operands {
+                op: "*".into(),
+                found: "a string and an integer".into()
+            }
+        );
+        assert_eq!(
+            lookup("zero").unwrap_err().kind,
+            EvalErrorKind::DivideByZero
+        );
+    }
+
+    #[test]
+    fn lazy() {
+        use crate::parse::grammar::Document;
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let files: MemoryResolver = vec![
+            ("broken.stn", "pub value: { x: 1 / 0 }"),
+            (
+                "main.stn",
+                r#"use broken.stn;
+                {
+                    tick: tick(),
+                    a: $tick + 1,
+                    b: [$a, $tick],
+                    bad: $broken.value.x,
+                    good: { port: 8080 },
+                }"#,
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files);
+        let url = loader.load("main.stn").unwrap();
+        let mut eval = Evaluator::new(&loader);
+        let ticks = Rc::new(Cell::new(0));
+        let counter = ticks.clone();
+        eval.register("tick", move || {
+            counter.set(counter.get() + 1);
+            counter.get()
+        });
+        match eval.get(&url, "good.port").unwrap().ast {
+            Ast::Literal(Literal::Int(n)) => assert_eq!(Number::from(n).to_string(), "8080"),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(ticks.get(), 0);
+        eval.get(&url, "b").unwrap();
+        eval.get(&url, "a").unwrap();
+        assert_eq!(ticks.get(), 1);
+        assert_eq!(
+            eval.get(&url, "bad").unwrap_err().kind,
+            EvalErrorKind::DivideByZero
+        );
+        assert_eq!(
+            eval.eval(&url).unwrap_err().kind,
+            EvalErrorKind::DivideByZero
+        );
+
+        let doc = Document::parse(
+            "use other.stn; use lib.stn::{port as p}; \
+             { servers: [{ port: 80 }], bad: $other.x, port: $p, shout: upper(\"hi\") }",
+        )
+        .unwrap();
+        match doc.get("servers.0.port").unwrap().ast {
+            Ast::Literal(Literal::Int(n)) => assert_eq!(Number::from(n).to_string(), "80"),
+            other => panic!("{:?}", other),
+        }
+        match doc.get("shout").unwrap().ast {
+            Ast::Literal(Literal::Str(s)) => assert_eq!(s, "HI"),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            doc.get("bad").unwrap_err().kind,
+            EvalErrorKind::Unloaded("other.stn".into())
+        );
+        assert_eq!(
+            doc.get("port").unwrap_err().kind,
+            EvalErrorKind::Unloaded("lib.stn".into())
+        );
+        assert_eq!(
+            doc.get("servers.1").unwrap_err().kind,
+            EvalErrorKind::BadPath("servers.1".into())
+        );
+    }
+
+    #[test]
+    fn pipelines() {
+        let files: MemoryResolver = vec![(
+            "main.stn",
+            r#"
+            loud(s: String) = upper($s);
+            pair(a, b) = "${a}${b}";
+            {
+                users: [{ name: "bo", age: 30 }, { name: "al", age: 12 }, { name: "cy", age: 18 }],
+                adults: $users ~ filter(`[age >= 18]`) ~ sort_by(`name`) ~ map(`name`) ~ join(", "),
+                slug: "Hello World" ~ lower ~ replace(" ", "-"),
+                first: $users ~ `[0].name` ~ upper,
+                short: $users ~ len < 5,
+                typed: 3 ~ upper,
+                unused: false && $users ~ missing,
+                shout: "hi" ~ loud,
+                joined: "a" ~ pair("b"),
+                mapped: map(["a"], loud),
+            }"#,
+        )]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files);
+        let url = loader.load("main.stn").unwrap();
+        let eval = Evaluator::new(&loader);
+        let lookup = |name| eval.lookup(&url, name, Span::default());
+        let literal = |name| match lookup(name).unwrap().ast {
+            Ast::Literal(literal) => literal,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(literal("adults"), Literal::Str("bo, cy".into()));
+        assert_eq!(literal("slug"), Literal::Str("hello-world".into()));
+        assert_eq!(literal("first"), Literal::Str("BO".into()));
+        assert_eq!(literal("short"), Literal::Bool(true));
+        assert_eq!(literal("unused"), Literal::Bool(false));
+        assert_eq!(literal("shout"), Literal::Str("HI".into()));
+        assert_eq!(literal("joined"), Literal::Str("ab".into()));
+        assert_eq!(
+            lookup("mapped").unwrap_err().kind,
+            EvalErrorKind::Argument {
+                function: "map".into(),
+                index: 2,
+                expected: "a function".into(),
+                found: "a template".into()
+            }
+        );
+        assert_eq!(
+            lookup("typed").unwrap_err().kind,
+            EvalErrorKind::Argument {
+                function: "upper".into(),
+                index: 1,
+                expected: "a string".into(),
+                found: "an integer".into()
+            }
+        );
+    }
+
+    #[test]
+    fn comprehensions() {
+        let files: MemoryResolver = vec![(
+            "main.stn",
+            r#"{
+                x: "outer",
+                names: ["bo", "al", "cy"],
+                ports: { web: 80, db: 5432 },
+                size: if len($names) > 2 { "big" } else { "small" },
+                loud: [upper(x) for x in $names if x != "al"],
+                numbered: ["${i}:${n}" for i, n in $names],
+                keys: [k for k in $ports],
+                shifted: { "${k}-alt": p + 1 for k, p in $ports if p < 1000 },
+                nested: [[x, $x] for x in $names if x == "cy"],
+                after: $x,
+                bad_cond: if 1 { 2 } else { 3 },
+                bad_items: [x for x in 3],
+                bad_key: { true: 1 for x in $names },
+            }"#,
+        )]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files);
+        let url = loader.load("main.stn").unwrap();
+        let eval = Evaluator::new(&loader);
+        let lookup = |name| eval.lookup(&url, name, Span::default());
+        let strs = |name| match lookup(name).unwrap().ast {
+            Ast::List(_, items) => items
+                .into_iter()
+                .map(|item| match item.ast {
+                    Ast::Literal(Literal::Str(s)) => s,
+                    other => panic!("{:?}", other),
+                })
+                .collect::<Vec<_>>(),
+            other => panic!("{:?}", other),
+        };
+        assert!(matches!(lookup("size").unwrap().ast, Ast::Literal(Literal::Str(s)) if s == "big"));
+        assert_eq!(strs("loud"), vec!["BO", "CY"]);
+        assert_eq!(strs("numbered"), vec!["0:bo", "1:al", "2:cy"]);
+        assert_eq!(strs("keys"), vec!["db", "web"]);
+        match lookup("shifted").unwrap().ast {
+            Ast::Map(None, fields) => {
+                assert_eq!(fields.keys().collect::<Vec<_>>(), vec!["web-alt"]);
+                match &fields["web-alt"].ast {
+                    Ast::Literal(Literal::Int(n)) => {
+                        assert_eq!(Number::from(n.clone()).to_f64(), 81.0)
+                    }
+                    other => panic!("{:?}", other),
+                }
+            }
+            other => panic!("{:?}", other),
+        }
+        match lookup("nested").unwrap().ast {
+            Ast::List(_, items) => match &items[0].ast {
+                Ast::List(_, pair) => assert!(matches!(
+                    &pair[1].ast,
+                    Ast::Literal(Literal::Str(s)) if s == "cy"
+                )),
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+        assert!(
+            matches!(lookup("after").unwrap().ast, Ast::Literal(Literal::Str(s)) if s == "outer")
+        );
+        assert_eq!(
+            lookup("bad_cond").unwrap_err().kind,
+            EvalErrorKind::Condition("an integer".into())
+        );
+        assert_eq!(
+            lookup("bad_items").unwrap_err().kind,
+            EvalErrorKind::NotIterable("an integer".into())
+        );
+        assert_eq!(
+            lookup("bad_key").unwrap_err().kind,
+            EvalErrorKind::KeyType("a boolean".into())
+        );
+    }
+
+    #[test]
+    fn widths() {
+        let files: MemoryResolver = vec![(
+            "main.stn",
+            r#"
+            type Small = u8;
+            enum Reading { Level(u8), Off }
+            class Port {
+                n: u16,
+                m: i16 = 200 + 100,
+                small: Small = 1,
+                levels: List<u8> = [],
+                reading: Reading = Reading::Off,
+            }
+            port(n: u8, m: u8 = 250 + 10) = $n;
+            {
+                base: 200,
+                sum: Port { n: $base + 100, levels: [$base, 2] },
+                over: Port { n: $base * 1000 },
+                alias: Port { n: 1, small: $base + 100 },
+                list: Port { n: 1, levels: [$base, $base + 56] },
+                variant: Port { n: 1, reading: Reading::Level($base + 100) },
+                arg: port($base + 55, 1),
+                default: port(1),
+            }"#,
+        )]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files);
+        let url = loader.load("main.stn").unwrap();
+        let eval = Evaluator::new(&loader);
+        let lookup = |name| eval.lookup(&url, name, Span::default());
+        let width = |ast: &Ast| match ast {
+            Ast::Literal(Literal::Int(n)) => (n.signed, n.bits.len()),
+            other => panic!("{:?}", other),
+        };
+        let sum = lookup("sum").unwrap();
+        assert_eq!(width(field(&sum, "n")), (false, 16));
+        assert_eq!(width(field(&sum, "m")), (true, 16));
+        assert_eq!(width(field(&sum, "small")), (false, 8));
+        match field(&sum, "levels") {
+            Ast::List(_, levels) => assert_eq!(width(&levels[0].ast), (false, 8)),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(width(&lookup("arg").unwrap().ast), (false, 8));
+        let out_of_range = |field: &str, value: &str, ty: &str| EvalErrorKind::OutOfRange {
+            field: field.into(),
+            value: value.into(),
+            ty: ty.into(),
+        };
+        assert_eq!(
+            lookup("over").unwrap_err().kind,
+            out_of_range("n", "200000", "u16")
+        );
+        assert_eq!(
+            lookup("alias").unwrap_err().kind,
+            out_of_range("small", "300", "u8")
+        );
+        assert_eq!(
+            lookup("list").unwrap_err().kind,
+            out_of_range("levels", "256", "u8")
+        );
+        assert_eq!(
+            lookup("variant").unwrap_err().kind,
+            out_of_range("reading", "300", "u8")
+        );
+        assert_eq!(
+            lookup("default").unwrap_err().kind,
+            out_of_range("m", "260", "u8")
+        );
+    }
+
+    #[test]
+    fn templates() {
+        let files: MemoryResolver = vec![
+            (
+                "cards.stn",
+                r#"
+                class Card { title: String, body: String, tags: List<String> = [] }
+                pub card(title: String, body = "${title}!") = Card { title: upper($title), body };
+                pub tagged(title, tags: List<String>) = Card { title: $title, body: "", tags: $tags };
+                "#,
+            ),
+            (
+                "main.stn",
+                r#"use cards.stn::{card, tagged};
+                {
+                    title: "outer",
+                    full: card("a", "b"),
+                    default: card("a"),
+                    tagged: tagged("t", ["x"]),
+                    many: [card(t) for t in ["p", "q"]],
+                    short: card(),
+                    long: card("a", "b", "c"),
+                    typed: card(1),
+                }"#,
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files);
+        let url = loader.load("main.stn").unwrap();
+        let eval = Evaluator::new(&loader);
+        let lookup = |name| eval.lookup(&url, name, Span::default());
+        let text = |node: &Node<'static>, key: &str| match field(node, key) {
+            Ast::Literal(Literal::Str(s)) => s.clone(),
+            other => panic!("{:?}", other),
+        };
+        let full = lookup("full").unwrap();
+        assert!(matches!(&full.ast, Ast::Map(Some(class), _) if class == "Card"));
+        assert_eq!(text(&full, "title"), "A");
+        assert_eq!(text(&full, "body"), "b");
+        assert!(matches!(field(&full, "tags"), Ast::List(_, tags) if tags.is_empty()));
+        assert_eq!(text(&lookup("default").unwrap(), "body"), "a!");
+        match field(&lookup("tagged").unwrap(), "tags") {
+            Ast::List(_, tags) => assert_eq!(tags.len(), 1),
+            other => panic!("{:?}", other),
+        }
+        match lookup("many").unwrap().ast {
+            Ast::List(_, cards) => assert_eq!(text(&cards[1], "title"), "Q"),
+            other => panic!("{:?}", other),
+        }
+        let arity = |found| EvalErrorKind::Arity {
+            function: "card".into(),
+            expected: if found == 0 { 1 } else { 2 },
+            variadic: false,
+            found,
+        };
+        assert_eq!(lookup("short").unwrap_err().kind, arity(0));
+        assert_eq!(lookup("long").unwrap_err().kind, arity(3));
+        assert_eq!(
+            lookup("typed").unwrap_err().kind,
+            EvalErrorKind::Argument {
+                function: "card".into(),
+                index: 1,
+                expected: "String".into(),
+                found: "an integer".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let files: MemoryResolver = vec![(
+            "main.stn",
+            r#"
+            deep(n) = if $n == 0 { "done" } else { deep($n - 1) };
+            forever(n) = forever($n);
+            {
+                shallow: deep(2),
+                nested: deep(100),
+                loop: forever(1),
+                word: "abcdefghij",
+                double: "${word}${word}",
+                big: [$double, $double, $double, $double, $double],
+                items: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+            }"#,
+        )]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files);
+        let url = loader.load("main.stn").unwrap();
+        let limit = |eval: &Evaluator<_>, name| match eval.get(&url, name) {
+            Err(EvalError {
+                kind: EvalErrorKind::Limit { limit, .. },
+                ..
+            }) => Some(limit),
+            Err(e) => panic!("{}", e),
+            Ok(_) => None,
+        };
+
+        let mut eval = Evaluator::new(&loader);
+        eval.set_limits(Limits {
+            depth: Some(64),
+            size: Some(80),
+            ..Limits::default()
+        });
+        assert_eq!(limit(&eval, "shallow"), None);
+        assert_eq!(limit(&eval, "nested"), Some(Limit::Depth));
+        assert_eq!(limit(&eval, "loop"), Some(Limit::Depth));
+        assert_eq!(limit(&eval, "double"), None);
+        assert_eq!(limit(&eval, "big"), Some(Limit::Size));
+
+        let mut eval = Evaluator::new(&loader);
+        eval.set_limits(Limits {
+            steps: Some(15),
+            ..Limits::default()
+        });
+        assert_eq!(limit(&eval, "items"), None);
+        assert_eq!(limit(&eval, "big"), Some(Limit::Steps));
+        // The steps are counted over every evaluation, so even small values now fail.
+        assert_eq!(limit(&eval, "word"), Some(Limit::Steps));
+
+        // Template calls are bounded by default, deeply enough for recursion that ends.
+        let eval = Evaluator::new(&loader);
+        assert_eq!(limit(&eval, "nested"), None);
+        assert_eq!(limit(&eval, "loop"), Some(Limit::Calls));
+
+        let mut eval = Evaluator::new(&loader);
+        eval.set_limits(Limits {
+            calls: Some(10),
+            ..Limits::default()
+        });
+        assert_eq!(limit(&eval, "shallow"), None);
+        assert_eq!(limit(&eval, "nested"), Some(Limit::Calls));
+    }
+}
@@ -0,0 +1,559 @@
+use super::{compare, display, float, kind, EvalErrorKind};
+use crate::lex::number::SizedNum;
+use crate::parse::selector::{Matcher, Step};
+use crate::parse::{Ast, Literal, Node, Span};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::iter;
+
+/// The type of a function's parameter, checked before the function is called.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Param {
+    Any,
+    Bool,
+    Str,
+    Int,
+    /// An integer or a float.
+    Number,
+    /// A list or an unnamed tuple.
+    List,
+    /// A dict or a class instance.
+    Dict,
+    /// A function's name, like `upper`; a call missing its first argument, like
+    /// `replace("-", "_")`; or a selector literal. See `Functions::apply`.
+    Function,
+}
+
+impl Param {
+    pub fn accepts(self, ast: &Ast) -> bool {
+        match (self, ast) {
+            (Param::Any, _) => true,
+            (Param::Bool, Ast::Literal(Literal::Bool(_))) => true,
+            (Param::Str, Ast::Literal(Literal::Str(_))) => true,
+            (Param::Int, Ast::Literal(Literal::Int(_))) => true,
+            (Param::Number, Ast::Literal(literal)) => float(literal).is_some(),
+            (Param::List, Ast::List(..)) | (Param::List, Ast::Tuple(None, _)) => true,
+            (Param::Dict, Ast::Map(..)) => true,
+            (Param::Function, Ast::Ident(_)) => true,
+            (Param::Function, Ast::Literal(Literal::Selector(_))) => true,
+            (Param::Function, ast) => ast.call().is_some(),
+            _ => false,
+        }
+    }
+
+    /// Describes the values this parameter accepts, for error messages.
+    pub fn describe(self) -> &'static str {
+        match self {
+            Param::Any => "a value",
+            Param::Bool => "a boolean",
+            Param::Str => "a string",
+            Param::Int => "an integer",
+            Param::Number => "a number",
+            Param::List => "a list",
+            Param::Dict => "a dict",
+            Param::Function => "a function",
+        }
+    }
+}
+
+type Body = dyn Fn(&Functions, Vec<Node<'static>>) -> Result<Ast<'static>, EvalErrorKind>;
+
+/// A function callable from a document as `name(args...)`.
+pub struct Function {
+    params: Vec<Param>,
+    /// The type of any further arguments after `params`, if it takes them.
+    rest: Option<Param>,
+    body: Box<Body>,
+}
+
+/// The functions a document can call, by name.
+pub struct Functions {
+    functions: HashMap<String, Function>,
+}
+
+impl Functions {
+    /// A registry with no functions in it, not even the built-ins.
+    pub fn new() -> Self {
+        Self {
+            functions: HashMap::new(),
+        }
+    }
+
+    /// Registers `body` as `name`, replacing any function already registered with that name.
+    ///
+    /// `body` is only called once the arguments match `params`, followed by any number of
+    /// `rest`.
+    pub fn insert(
+        &mut self,
+        name: &str,
+        params: Vec<Param>,
+        rest: Option<Param>,
+        body: impl Fn(&Functions, Vec<Node<'static>>) -> Result<Ast<'static>, EvalErrorKind> + 'static,
+    ) {
+        let function = Function {
+            params,
+            rest,
+            body: Box::new(body),
+        };
+        self.functions.insert(name.to_string(), function);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+
+    /// The type of the argument at `index` to the function `name`.
+    pub fn param(&self, name: &str, index: usize) -> Option<Param> {
+        let function = self.functions.get(name)?;
+        function.params.get(index).copied().or(function.rest)
+    }
+
+    /// Checks `args` against the parameters of the function `name`, then calls it.
+    pub fn call(
+        &self,
+        name: &str,
+        args: Vec<Node<'static>>,
+    ) -> Result<Ast<'static>, EvalErrorKind> {
+        let function = self
+            .functions
+            .get(name)
+            .ok_or_else(|| EvalErrorKind::UnknownFunction(name.to_string()))?;
+        let len = function.params.len();
+        if args.len() < len || (function.rest.is_none() && args.len() > len) {
+            return Err(EvalErrorKind::Arity {
+                function: name.to_string(),
+                expected: len,
+                variadic: function.rest.is_some(),
+                found: args.len(),
+            });
+        }
+        for (i, arg) in args.iter().enumerate() {
+            let param = function.params.get(i).copied().or(function.rest).unwrap();
+            if !param.accepts(&arg.ast) {
+                return Err(EvalErrorKind::Argument {
+                    function: name.to_string(),
+                    index: i + 1,
+                    expected: param.describe().to_string(),
+                    found: kind(&arg.ast).to_string(),
+                });
+            }
+        }
+        (function.body)(self, args)
+    }
+
+    /// Applies a `Param::Function` argument to `value`.
+    ///
+    /// A function's name calls it with `value` as its only argument, and a call like
+    /// `replace("-", "_")` calls it with `value` before its other arguments. A selector made of
+    /// one predicate, like `` `[age >= 18]` ``, tests whether `value` passes it; any other selector
+    /// returns the first value it selects from `value`.
+    pub fn apply(
+        &self,
+        f: &Node<'static>,
+        value: Node<'static>,
+    ) -> Result<Ast<'static>, EvalErrorKind> {
+        match &f.ast {
+            Ast::Ident(name) => self.call(name, vec![value]),
+            Ast::Literal(Literal::Selector(selector)) => match &selector.steps[..] {
+                [Step {
+                    descendant: false,
+                    matcher: Matcher::Predicate(predicate),
+                }] => Ok(Ast::Literal(Literal::Bool(predicate.test(&value.ast)))),
+                _ => match selector.select(&value.ast).first() {
+                    Some(selected) => Ok((*selected).clone()),
+                    None => Err(EvalErrorKind::Call {
+                        function: selector.to_string(),
+                        message: format!("nothing selected from {}", kind(&value.ast)),
+                    }),
+                },
+            },
+            ast => match ast.call() {
+                Some((name, args)) => self.call(
+                    name,
+                    iter::once(value).chain(args.iter().cloned()).collect(),
+                ),
+                None => Err(EvalErrorKind::Operands {
+                    op: "()".into(),
+                    found: kind(ast).into(),
+                }),
+            },
+        }
+    }
+
+    /// The built-in functions:
+    ///
+    /// - strings: `upper(s)`, `lower(s)`, `replace(s, from, to)`, `split(s, sep)`,
+    ///   `join(list, sep)` and `format(template, args...)`, which fills each `{}` in turn;
+    /// - numbers: `min(n, ns...)`, `max(n, ns...)`, `clamp(n, low, high)` and `round(n)`;
+    /// - collections: `len(x)`, `map(list, f)`, `filter(list, f)`, `sort_by(list, f)`,
+    ///   `keys(dict)` and `values(dict)`, in key order;
+    /// - conversions: `to_int(x)` and `to_string(x)`.
+    pub fn builtins() -> Self {
+        use Param::*;
+        let mut functions = Self::new();
+        functions.insert("upper", vec![Str], None, |_, args| {
+            Ok(text(string(&args[0]).to_uppercase()))
+        });
+        functions.insert("lower", vec![Str], None, |_, args| {
+            Ok(text(string(&args[0]).to_lowercase()))
+        });
+        functions.insert("replace", vec![Str, Str, Str], None, |_, args| {
+            let (s, from, to) = (string(&args[0]), string(&args[1]), string(&args[2]));
+            Ok(text(s.replace(from, to)))
+        });
+        functions.insert("split", vec![Str, Str], None, |_, args| {
+            let parts = string(&args[0])
+                .split(string(&args[1]))
+                .map(|part| Node::new(Span::default(), text(part.to_string())))
+                .collect();
+            Ok(Ast::List(None, parts))
+        });
+        functions.insert("join", vec![List, Str], None, |_, args| {
+            let mut parts = Vec::new();
+            for item in items(&args[0]) {
+                parts.push(to_text("join", item)?);
+            }
+            Ok(text(parts.join(string(&args[1]))))
+        });
+        functions.insert("format", vec![Str], Some(Any), |_, args| {
+            let template = string(&args[0]);
+            let pieces: Vec<_> = template.split("{}").collect();
+            if pieces.len() != args.len() {
+                return Err(call_error(
+                    "format",
+                    format!(
+                        "the template has {} `{{}}` but there are {} values",
+                        pieces.len() - 1,
+                        args.len() - 1
+                    ),
+                ));
+            }
+            let mut result = pieces[0].to_string();
+            for (arg, piece) in args[1..].iter().zip(&pieces[1..]) {
+                result.push_str(&to_text("format", arg)?);
+                result.push_str(piece);
+            }
+            Ok(text(result))
+        });
+        functions.insert("min", vec![Number], Some(Number), |_, args| {
+            extreme("min", args, Ordering::Less)
+        });
+        functions.insert("max", vec![Number], Some(Number), |_, args| {
+            extreme("max", args, Ordering::Greater)
+        });
+        functions.insert("clamp", vec![Number, Number, Number], None, |_, args| {
+            let mut args = args.into_iter();
+            let (n, low, high) = (args.next(), args.next(), args.next());
+            let n = extreme("clamp", vec![n.unwrap(), high.unwrap()], Ordering::Less)?;
+            extreme(
+                "clamp",
+                vec![Node::new(Span::default(), n), low.unwrap()],
+                Ordering::Greater,
+            )
+        });
+        functions.insert("round", vec![Number], None, |_, args| match &args[0].ast {
+            Ast::Literal(Literal::Int(_)) => Ok(args[0].ast.clone()),
+            Ast::Literal(literal) => {
+                let rounded = float(literal).unwrap().round();
+                if rounded.abs() >= 2f64.powi(127) || rounded.is_nan() {
+                    return Err(call_error("round", format!("{} is out of range", rounded)));
+                }
+                Ok(int(rounded as i128))
+            }
+            _ => unreachable!(),
+        });
+        functions.insert("len", vec![Any], None, |_, args| {
+            let len = match &args[0].ast {
+                Ast::Literal(Literal::Str(s)) => s.chars().count(),
+                Ast::List(_, items) | Ast::Tuple(_, items) => items.len(),
+                Ast::Map(_, fields) => fields.len(),
+                other => {
+                    return Err(EvalErrorKind::Argument {
+                        function: "len".into(),
+                        index: 1,
+                        expected: "a string, list or dict".into(),
+                        found: kind(other).into(),
+                    })
+                }
+            };
+            Ok(int(len as i128))
+        });
+        functions.insert("map", vec![List, Function], None, |functions, args| {
+            let mut mapped = Vec::new();
+            for item in items(&args[0]) {
+                let ast = functions.apply(&args[1], item.clone())?;
+                mapped.push(Node::new(item.span, ast));
+            }
+            Ok(Ast::List(None, mapped))
+        });
+        functions.insert("filter", vec![List, Function], None, |functions, args| {
+            let mut kept = Vec::new();
+            for item in items(&args[0]) {
+                match functions.apply(&args[1], item.clone())? {
+                    Ast::Literal(Literal::Bool(true)) => kept.push(item.clone()),
+                    Ast::Literal(Literal::Bool(false)) => (),
+                    other => {
+                        let message = format!("the filter returned {}", kind(&other));
+                        return Err(call_error("filter", message));
+                    }
+                }
+            }
+            Ok(Ast::List(None, kept))
+        });
+        functions.insert("sort_by", vec![List, Function], None, |functions, args| {
+            let mut keyed = Vec::new();
+            for item in items(&args[0]) {
+                keyed.push((functions.apply(&args[1], item.clone())?, item.clone()));
+            }
+            let mut error = None;
+            keyed.sort_by(|(a, _), (b, _)| match order(a, b) {
+                Some(ordering) => ordering.unwrap_or(Ordering::Equal),
+                None => {
+                    error.get_or_insert((kind(a), kind(b)));
+                    Ordering::Equal
+                }
+            });
+            if let Some((a, b)) = error {
+                return Err(call_error(
+                    "sort_by",
+                    format!("can't compare {} and {}", a, b),
+                ));
+            }
+            Ok(Ast::List(
+                None,
+                keyed.into_iter().map(|(_, item)| item).collect(),
+            ))
+        });
+        functions.insert("keys", vec![Dict], None, |_, args| {
+            let keys = entries(&args[0])
+                .into_iter()
+                .map(|(key, value)| Node::new(value.span, text(key.clone())))
+                .collect();
+            Ok(Ast::List(None, keys))
+        });
+        functions.insert("values", vec![Dict], None, |_, args| {
+            let values = entries(&args[0])
+                .into_iter()
+                .map(|(_, value)| value.clone())
+                .collect();
+            Ok(Ast::List(None, values))
+        });
+        functions.insert("to_int", vec![Any], None, |_, args| {
+            let n = match &args[0].ast {
+                Ast::Literal(Literal::Int(_)) => return Ok(args[0].ast.clone()),
+                Ast::Literal(Literal::Bool(b)) => Some(if *b { 1 } else { 0 }),
+                Ast::Literal(Literal::Str(s)) => s.trim().parse().ok().filter(|n| *n != i128::MIN),
+                Ast::Literal(literal) => float(literal)
+                    .map(f64::trunc)
+                    .filter(|f| f.abs() < 2f64.powi(127))
+                    .map(|f| f as i128),
+                _ => None,
+            };
+            match n {
+                Some(n) => Ok(int(n)),
+                None => Err(call_error(
+                    "to_int",
+                    format!("{} can't be converted to an integer", kind(&args[0].ast)),
+                )),
+            }
+        });
+        functions.insert("to_string", vec![Any], None, |_, args| {
+            Ok(text(to_text("to_string", &args[0])?))
+        });
+        functions
+    }
+}
+
+impl Default for Functions {
+    fn default() -> Self {
+        Self::builtins()
+    }
+}
+
+pub(super) fn call_error(function: &str, message: impl Into<String>) -> EvalErrorKind {
+    EvalErrorKind::Call {
+        function: function.to_string(),
+        message: message.into(),
+    }
+}
+
+fn text(s: String) -> Ast<'static> {
+    Ast::Literal(Literal::Str(s))
+}
+
+fn int(n: i128) -> Ast<'static> {
+    // `i128::MIN` is the only value that doesn't fit, and every caller rules it out.
+    Ast::Literal(Literal::Int(SizedNum::from_i128(n).unwrap()))
+}
+
+/// The contents of an argument already checked to be a `Param::Str`.
+fn string<'a>(node: &'a Node) -> &'a str {
+    match &node.ast {
+        Ast::Literal(Literal::Str(s)) => s,
+        _ => unreachable!(),
+    }
+}
+
+/// The items of an argument already checked to be a `Param::List`.
+fn items<'a, 's>(node: &'a Node<'s>) -> &'a [Node<'s>] {
+    match &node.ast {
+        Ast::List(_, items) | Ast::Tuple(_, items) => items,
+        _ => unreachable!(),
+    }
+}
+
+/// The entries of an argument already checked to be a `Param::Dict`, sorted by key.
+fn entries<'a, 's>(node: &'a Node<'s>) -> Vec<(&'a String, &'a Node<'s>)> {
+    let mut entries: Vec<_> = match &node.ast {
+        Ast::Map(_, fields) => fields.iter().collect(),
+        _ => unreachable!(),
+    };
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+/// Writes a string, number or boolean as text, as it's interpolated into a string.
+fn to_text(function: &str, node: &Node) -> Result<String, EvalErrorKind> {
+    match &node.ast {
+        Ast::Literal(literal) => Ok(display(literal.clone())),
+        other => Err(call_error(
+            function,
+            format!("{} can't be written as text", kind(other)),
+        )),
+    }
+}
+
+/// Orders two values as `compare` does, or returns `None` if either isn't a literal.
+fn order(a: &Ast, b: &Ast) -> Option<Option<Ordering>> {
+    match (a, b) {
+        (Ast::Literal(a), Ast::Literal(b)) => compare(a, b),
+        _ => None,
+    }
+}
+
+/// The first of `args` that's furthest towards `towards` from the rest.
+fn extreme(
+    function: &str,
+    args: Vec<Node<'static>>,
+    towards: Ordering,
+) -> Result<Ast<'static>, EvalErrorKind> {
+    let mut args = args.into_iter();
+    let mut best = args.next().unwrap().ast;
+    for arg in args {
+        match order(&arg.ast, &best) {
+            Some(Some(ordering)) if ordering == towards => best = arg.ast,
+            Some(_) => (),
+            None => {
+                let message = format!("can't compare {} and {}", kind(&arg.ast), kind(&best));
+                return Err(call_error(function, message));
+            }
+        }
+    }
+    Ok(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::{EvalErrorKind, Evaluator};
+    use crate::parse::{Ast, Literal, Span};
+    use crate::resolve::{Loader, MemoryResolver};
+
+    #[test]
+    fn builtins() {
+        let files: MemoryResolver = vec![(
+            "main.stn",
+            r#"{
+                names: ["web-api", "db", "cache-2"],
+                users: [{ name: "b", age: 30 }, { name: "a", age: 12 }, { name: "c", age: 18 }],
+                shout: upper("hi"),
+                snake: map($names, replace("-", "_")),
+                joined: join(map($names, upper), ", "),
+                parts: len(split("a.b.c", ".")),
+                label: format("{} of {}", 3, "four"),
+                least: min(3, 1.5, 2),
+                clamped: clamp(15, 0, 10),
+                rounded: round(2.5),
+                adults: join(map(filter($users, `[age >= 18]`), `name`), ""),
+                sorted: join(map(sort_by($users, `name`), `name`), ""),
+                by_age: join(map(sort_by($users, `age`), `name`), ""),
+                keys: join(keys({ b: 1, a: 2 }), ""),
+                total: to_int("40") + to_int(2.9),
+                text: to_string(8080) + "/",
+                unknown: shout("x"),
+                arity: upper("a", "b"),
+                typed: upper(1),
+                failed: to_int("x"),
+                overflow: to_int("-170141183460469231731687303715884105728"),
+                variant: Some(1),
+            }"#,
+        )]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files);
+        let url = loader.load("main.stn").unwrap();
+        let eval = Evaluator::new(&loader);
+        let lookup = |name| eval.lookup(&url, name, Span::default());
+        let text = |name| match lookup(name).unwrap().ast {
+            Ast::Literal(Literal::Str(s)) => s,
+            other => panic!("{:?}", other),
+        };
+        let number = |name| match lookup(name).unwrap().ast {
+            Ast::Literal(literal) => super::float(&literal).unwrap(),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(text("shout"), "HI");
+        match lookup("snake").unwrap().ast {
+            Ast::List(None, items) => match &items[0].ast {
+                Ast::Literal(Literal::Str(s)) => assert_eq!(s, "web_api"),
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(text("joined"), "WEB-API, DB, CACHE-2");
+        assert_eq!(number("parts"), 3.0);
+        assert_eq!(text("label"), "3 of four");
+        assert_eq!(number("least"), 1.5);
+        assert_eq!(number("clamped"), 10.0);
+        assert_eq!(number("rounded"), 3.0);
+        assert_eq!(text("adults"), "bc");
+        assert_eq!(text("sorted"), "abc");
+        assert_eq!(text("by_age"), "acb");
+        assert_eq!(text("keys"), "ab");
+        assert_eq!(number("total"), 42.0);
+        assert_eq!(text("text"), "8080/");
+        assert_eq!(
+            lookup("unknown").unwrap_err().kind,
+            EvalErrorKind::UnknownFunction("shout".into())
+        );
+        assert_eq!(
+            lookup("arity").unwrap_err().kind,
+            EvalErrorKind::Arity {
+                function: "upper".into(),
+                expected: 1,
+                variadic: false,
+                found: 2
+            }
+        );
+        assert_eq!(
+            lookup("typed").unwrap_err().kind,
+            EvalErrorKind::Argument {
+                function: "upper".into(),
+                index: 1,
+                expected: "a string".into(),
+                found: "an integer".into()
+            }
+        );
+        assert!(matches!(
+            lookup("failed").unwrap_err().kind,
+            EvalErrorKind::Call { .. }
+        ));
+        assert!(matches!(
+            lookup("overflow").unwrap_err().kind,
+            EvalErrorKind::Call { .. }
+        ));
+        assert!(matches!(
+            lookup("variant").unwrap().ast,
+            Ast::Tuple(Some(_), _)
+        ));
+    }
+}
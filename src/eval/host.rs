@@ -0,0 +1,448 @@
+use super::functions::{call_error, Functions, Param};
+use super::{float, kind, EvalErrorKind};
+use crate::lex::number::SizedNum;
+use crate::parse::{Ast, Literal, Node, Span};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Display;
+
+/// A Rust type a host function can take as an argument.
+pub trait FromAst: Sized {
+    /// The parameter type arguments are checked against before they're converted.
+    fn param() -> Param;
+
+    /// Describes the values this converts from, for error messages.
+    fn expected() -> String {
+        Self::param().describe().to_string()
+    }
+
+    /// Converts an argument that `param` accepts, or returns `None` if it still doesn't fit,
+    /// like an integer out of range.
+    fn from_ast(node: Node<'static>) -> Option<Self>;
+}
+
+/// A Rust type a host function can return.
+pub trait IntoAst {
+    /// Converts the value, or returns why the function failed.
+    fn into_ast(self) -> Result<Ast<'static>, String>;
+}
+
+impl FromAst for Node<'static> {
+    fn param() -> Param {
+        Param::Any
+    }
+
+    fn from_ast(node: Node<'static>) -> Option<Self> {
+        Some(node)
+    }
+}
+
+impl FromAst for Ast<'static> {
+    fn param() -> Param {
+        Param::Any
+    }
+
+    fn from_ast(node: Node<'static>) -> Option<Self> {
+        Some(node.ast)
+    }
+}
+
+impl FromAst for String {
+    fn param() -> Param {
+        Param::Str
+    }
+
+    fn from_ast(node: Node<'static>) -> Option<Self> {
+        match node.ast {
+            Ast::Literal(Literal::Str(s)) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl FromAst for bool {
+    fn param() -> Param {
+        Param::Bool
+    }
+
+    fn from_ast(node: Node<'static>) -> Option<Self> {
+        match node.ast {
+            Ast::Literal(Literal::Bool(b)) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+impl FromAst for f64 {
+    fn param() -> Param {
+        Param::Number
+    }
+
+    fn from_ast(node: Node<'static>) -> Option<Self> {
+        match &node.ast {
+            Ast::Literal(literal) => float(literal),
+            _ => None,
+        }
+    }
+}
+
+impl FromAst for f32 {
+    fn param() -> Param {
+        Param::Number
+    }
+
+    fn from_ast(node: Node<'static>) -> Option<Self> {
+        f64::from_ast(node).map(|f| f as f32)
+    }
+}
+
+impl<T: FromAst> FromAst for Vec<T> {
+    fn param() -> Param {
+        Param::List
+    }
+
+    fn expected() -> String {
+        format!("a list of {}", plural(&T::expected()))
+    }
+
+    fn from_ast(node: Node<'static>) -> Option<Self> {
+        match node.ast {
+            Ast::List(_, items) | Ast::Tuple(None, items) => items
+                .into_iter()
+                .map(|item| {
+                    if !T::param().accepts(&item.ast) {
+                        return None;
+                    }
+                    T::from_ast(item)
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+impl<T: FromAst> FromAst for HashMap<String, T> {
+    fn param() -> Param {
+        Param::Dict
+    }
+
+    fn expected() -> String {
+        format!("a dict of {}", plural(&T::expected()))
+    }
+
+    fn from_ast(node: Node<'static>) -> Option<Self> {
+        match node.ast {
+            Ast::Map(_, fields) => fields
+                .into_iter()
+                .map(|(key, value)| {
+                    if !T::param().accepts(&value.ast) {
+                        return None;
+                    }
+                    Some((key, T::from_ast(value)?))
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+/// "a string" -> "strings", for describing collections.
+fn plural(expected: &str) -> String {
+    let noun = expected.trim_start_matches("an ").trim_start_matches("a ");
+    format!("{}s", noun)
+}
+
+impl IntoAst for Ast<'static> {
+    fn into_ast(self) -> Result<Ast<'static>, String> {
+        Ok(self)
+    }
+}
+
+impl IntoAst for Node<'static> {
+    fn into_ast(self) -> Result<Ast<'static>, String> {
+        Ok(self.ast)
+    }
+}
+
+impl IntoAst for String {
+    fn into_ast(self) -> Result<Ast<'static>, String> {
+        Ok(Ast::Literal(Literal::Str(self)))
+    }
+}
+
+impl IntoAst for &str {
+    fn into_ast(self) -> Result<Ast<'static>, String> {
+        self.to_string().into_ast()
+    }
+}
+
+impl IntoAst for bool {
+    fn into_ast(self) -> Result<Ast<'static>, String> {
+        Ok(Ast::Literal(Literal::Bool(self)))
+    }
+}
+
+impl IntoAst for f64 {
+    fn into_ast(self) -> Result<Ast<'static>, String> {
+        Ok(Ast::Literal(Literal::F64(self)))
+    }
+}
+
+impl IntoAst for f32 {
+    fn into_ast(self) -> Result<Ast<'static>, String> {
+        Ok(Ast::Literal(Literal::F32(self)))
+    }
+}
+
+macro_rules! conv_int {
+    ($($t:ty),*) => {
+        $(
+            impl FromAst for $t {
+                fn param() -> Param {
+                    Param::Int
+                }
+
+                fn expected() -> String {
+                    format!("an integer that fits in `{}`", stringify!($t))
+                }
+
+                fn from_ast(node: Node<'static>) -> Option<Self> {
+                    match node.ast {
+                        Ast::Literal(Literal::Int(n)) => <$t>::try_from(n.to_i128()?).ok(),
+                        _ => None,
+                    }
+                }
+            }
+
+            impl IntoAst for $t {
+                fn into_ast(self) -> Result<Ast<'static>, String> {
+                    let n = i128::try_from(self)
+                        .ok()
+                        .and_then(SizedNum::from_i128)
+                        .ok_or_else(|| format!("{} is too large", self))?;
+                    Ok(Ast::Literal(Literal::Int(n)))
+                }
+            }
+        )*
+    };
+}
+
+conv_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64, i128, isize);
+
+impl<T: IntoAst> IntoAst for Vec<T> {
+    fn into_ast(self) -> Result<Ast<'static>, String> {
+        let items = self
+            .into_iter()
+            .map(|item| Ok(Node::new(Span::default(), item.into_ast()?)))
+            .collect::<Result<_, String>>()?;
+        Ok(Ast::List(None, items))
+    }
+}
+
+impl<T: IntoAst> IntoAst for HashMap<String, T> {
+    fn into_ast(self) -> Result<Ast<'static>, String> {
+        let fields = self
+            .into_iter()
+            .map(|(key, value)| Ok((key, Node::new(Span::default(), value.into_ast()?))))
+            .collect::<Result<_, String>>()?;
+        Ok(Ast::Map(None, fields))
+    }
+}
+
+/// `None` or `Some(value)`, as an optional field is written.
+impl<T: IntoAst> IntoAst for Option<T> {
+    fn into_ast(self) -> Result<Ast<'static>, String> {
+        match self {
+            None => Ok(Ast::Ident("None".into())),
+            Some(value) => {
+                let value = Node::new(Span::default(), value.into_ast()?);
+                Ok(Ast::Tuple(Some("Some".into()), vec![value]))
+            }
+        }
+    }
+}
+
+/// `Err` fails the call with its message.
+impl<T: IntoAst, E: Display> IntoAst for Result<T, E> {
+    fn into_ast(self) -> Result<Ast<'static>, String> {
+        self.map_err(|e| e.to_string())?.into_ast()
+    }
+}
+
+/// A Rust function or closure that can be registered with `Functions::register`: one taking
+/// up to six `FromAst` arguments and returning an `IntoAst`.
+pub trait HostFunction<Args>: 'static {
+    fn params() -> Vec<Param>;
+
+    /// Converts `args`, which have already been checked against `params`, and calls the
+    /// function with them.
+    fn invoke(&self, name: &str, args: Vec<Node<'static>>) -> Result<Ast<'static>, EvalErrorKind>;
+}
+
+/// Converts the argument at `index` to the function `name`.
+fn convert<T: FromAst>(name: &str, index: usize, node: Node<'static>) -> Result<T, EvalErrorKind> {
+    let found = kind(&node.ast);
+    T::from_ast(node).ok_or_else(|| EvalErrorKind::Argument {
+        function: name.to_string(),
+        index: index + 1,
+        expected: T::expected(),
+        found: found.to_string(),
+    })
+}
+
+macro_rules! host_function {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg),*> HostFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: IntoAst,
+            $($arg: FromAst,)*
+        {
+            fn params() -> Vec<Param> {
+                vec![$($arg::param()),*]
+            }
+
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn invoke(
+                &self,
+                name: &str,
+                args: Vec<Node<'static>>,
+            ) -> Result<Ast<'static>, EvalErrorKind> {
+                let mut args = args.into_iter().enumerate();
+                $(
+                    let (i, arg) = args.next().unwrap();
+                    let $arg: $arg = convert(name, i, arg)?;
+                )*
+                self($($arg),*)
+                    .into_ast()
+                    .map_err(|message| call_error(name, message))
+            }
+        }
+    };
+}
+
+host_function!();
+host_function!(A);
+host_function!(A, B);
+host_function!(A, B, C);
+host_function!(A, B, C, D);
+host_function!(A, B, C, D, E);
+host_function!(A, B, C, D, E, G);
+
+impl Functions {
+    /// Registers a Rust function as `name`, replacing any function already registered with that
+    /// name. Its arguments are checked and converted with `FromAst`, and its result converted
+    /// back with `IntoAst`:
+    ///
+    /// ```ignore
+    /// functions.register("lookup", |id: String| users.get(&id).cloned().ok_or("no such user"));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If `name` doesn't start with a lowercase letter, since a document couldn't call it: it
+    /// would be read as a variant.
+    pub fn register<Args, F: HostFunction<Args>>(&mut self, name: &str, f: F) {
+        assert!(
+            name.starts_with(char::is_lowercase),
+            "function names must start with a lowercase letter, not `{}`",
+            name
+        );
+        let owned = name.to_string();
+        self.insert(name, F::params(), None, move |_, args| {
+            f.invoke(&owned, args)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::{EvalErrorKind, Evaluator};
+    use crate::parse::{Ast, Literal, Span};
+    use crate::resolve::{Loader, MemoryResolver};
+    use std::collections::HashMap;
+
+    #[test]
+    fn register() {
+        let files: MemoryResolver = vec![(
+            "main.stn",
+            r#"{
+                name: our_lookup("7"),
+                missing: our_lookup("8"),
+                scaled: scale([1, 2.5], 2),
+                total: total({ a: 1, b: 2 }),
+                shouted: map(["a", "b"], shout(3)),
+                range: scale([1], 300),
+                typed: scale(["a"], 2),
+                later: our_lookup("7") + "!",
+            }"#,
+        )]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files);
+        let url = loader.load("main.stn").unwrap();
+        let mut eval = Evaluator::new(&loader);
+        let users: HashMap<_, _> = vec![("7".to_string(), "Ada".to_string())]
+            .into_iter()
+            .collect();
+        eval.register("our_lookup", move |id: String| {
+            users.get(&id).cloned().ok_or(format!("no user {}", id))
+        });
+        eval.register("scale", |xs: Vec<f64>, by: u8| {
+            xs.into_iter()
+                .map(|x| x * f64::from(by))
+                .collect::<Vec<_>>()
+        });
+        eval.register("total", |counts: HashMap<String, i64>| {
+            counts.values().sum::<i64>()
+        });
+        eval.register("shout", |s: String, n: usize| {
+            s.to_uppercase() + &"!".repeat(n)
+        });
+
+        let lookup = |name| eval.lookup(&url, name, Span::default());
+        let literal = |name| match lookup(name).unwrap().ast {
+            Ast::Literal(literal) => literal,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(literal("name"), Literal::Str("Ada".into()));
+        assert_eq!(literal("later"), Literal::Str("Ada!".into()));
+        assert_eq!(literal("total"), Literal::Int(3u8.into()));
+        match lookup("scaled").unwrap().ast {
+            Ast::List(None, items) => assert_eq!(items.len(), 2),
+            other => panic!("{:?}", other),
+        }
+        match lookup("shouted").unwrap().ast {
+            Ast::List(None, items) => match &items[1].ast {
+                Ast::Literal(Literal::Str(s)) => assert_eq!(s, "B!!!"),
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            lookup("missing").unwrap_err().kind,
+            EvalErrorKind::Call {
+                function: "our_lookup".into(),
+                message: "no user 8".into()
+            }
+        );
+        assert_eq!(
+            lookup("range").unwrap_err().kind,
+            EvalErrorKind::Argument {
+                function: "scale".into(),
+                index: 2,
+                expected: "an integer that fits in `u8`".into(),
+                found: "an integer".into()
+            }
+        );
+        assert_eq!(
+            lookup("typed").unwrap_err().kind,
+            EvalErrorKind::Argument {
+                function: "scale".into(),
+                index: 1,
+                expected: "a list of numbers".into(),
+                found: "a list".into()
+            }
+        );
+    }
+}
@@ -1,4 +1,3 @@
-<<<<<<< HEAD
 use std::collections::VecDeque;
 use std::convert::TryInto;
 use std::fmt::Debug;
@@ -40,6 +39,83 @@ impl SizedNum {
     pub fn is_neg(&self) -> bool {
         self.signed && self.bits[0]
     }
+
+    /// The bits of the number's magnitude, without its sign bit or any leading zeroes.
+    ///
+    /// A sign bit over a zero magnitude is `iN`'s minimum, -2^(N-1), as in two's complement, so
+    /// its magnitude is all of its bits.
+    pub fn magnitude(&self) -> &[bool] {
+        let bits = if self.signed {
+            &self.bits[1..]
+        } else {
+            &self.bits[..]
+        };
+        if self.is_neg() && !bits.contains(&true) {
+            return &self.bits[..];
+        }
+        let first = bits.iter().position(|b| *b).unwrap_or(bits.len());
+        &bits[first..]
+    }
+
+    /// The same number in `size` bits, or `None` if it doesn't fit.
+    ///
+    /// Signed numbers keep a sign bit and a magnitude, so `iN` holds ±(2^(N-1) - 1), plus
+    /// -2^(N-1) as a sign bit over a zero magnitude.
+    pub fn resize(&self, signed: bool, size: usize) -> Option<Self> {
+        let magnitude = self.magnitude();
+        let neg = self.is_neg() && !magnitude.is_empty();
+        if !(1..=128).contains(&size) || (neg && !signed) {
+            return None;
+        }
+        let room = if signed { size - 1 } else { size };
+        if neg && magnitude.len() == room + 1 && !magnitude[1..].contains(&true) {
+            let mut bits = vec![false; size];
+            bits[0] = true;
+            return Some(Self::from_bits(true, bits));
+        }
+        if magnitude.len() > room {
+            return None;
+        }
+        let mut bits = Vec::with_capacity(size);
+        if signed {
+            bits.push(neg);
+        }
+        bits.extend((0..room - magnitude.len()).map(|_| false));
+        bits.extend_from_slice(magnitude);
+        Some(Self::from_bits(signed, bits))
+    }
+
+    /// The same number in the fewest bits that can hold it.
+    pub fn minimal(&self) -> Self {
+        let size = self.magnitude().len() + if self.signed { 1 } else { 0 };
+        self.resize(self.signed, size.max(1)).unwrap()
+    }
+
+    /// The value as an `i128`, or `None` if it's out of range.
+    pub fn to_i128(&self) -> Option<i128> {
+        let magnitude = self.magnitude();
+        if self.is_neg() && magnitude.len() == 128 && !magnitude[1..].contains(&true) {
+            return Some(i128::MIN);
+        }
+        if magnitude.len() > 127 {
+            return None;
+        }
+        let n = magnitude
+            .iter()
+            .fold(0i128, |n, bit| n << 1 | if *bit { 1 } else { 0 });
+        Some(if self.is_neg() { -n } else { n })
+    }
+
+    /// `n` in the fewest bits that can hold it, as it would be written as a literal: unsigned
+    /// unless it's negative. `None` for `i128::MIN`, whose magnitude needs 128 bits.
+    pub fn from_i128(n: i128) -> Option<Self> {
+        match n {
+            0 => Some(Self::zero(false, 1)),
+            i128::MIN => None,
+            n if n < 0 => Some(Self::from(n)),
+            n => Some(Self::from(n as u128)),
+        }
+    }
 }
 
 impl Debug for SizedNum {
@@ -167,6 +243,27 @@ impl Into<String> for Number {
     }
 }
 
+impl Number {
+    /// The value as an `f64`, rounding integers too large to represent exactly.
+    pub fn to_f64(self) -> f64 {
+        use Number::*;
+        match self {
+            U8(n) => n.into(),
+            U16(n) => n.into(),
+            U32(n) => n.into(),
+            U64(n) => n as f64,
+            U128(n) => n as f64,
+            I8(n) => n.into(),
+            I16(n) => n.into(),
+            I32(n) => n.into(),
+            I64(n) => n as f64,
+            I128(n) => n as f64,
+            F32(n) => n.into(),
+            F64(n) => n,
+        }
+    }
+}
+
 impl Display for Number {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let s: String = (*self).into();
@@ -185,7 +282,10 @@ macro_rules! from_sized {
                     res += TWO.pow(i as _);
                 }
             }
-            if $s.is_neg() {
+            if $s.is_neg() && res == 0 {
+                // A sign bit over a zero magnitude is the minimum, as in two's complement.
+                res = <$i>::MIN >> (std::mem::size_of::<$i>() * 8 - $s.bits.len());
+            } else if $s.is_neg() {
                 res = -res
             }
             Number::from(res)
@@ -273,281 +373,44 @@ mod tests {
         let ret: i16 = sized.into();
         assert_eq!(ret, 256i16);
     }
+
+    #[test]
+    fn resize() {
+        let sized = SizedNum::from(100u8);
+        assert_eq!(sized.bits.len(), 7);
+        assert!(sized.resize(false, 6).is_none());
+        let wide = sized.resize(false, 12).unwrap();
+        assert_eq!(wide.bits.len(), 12);
+        assert_eq!(wide.minimal(), sized);
+        let ret: u16 = wide.into();
+        assert_eq!(ret, 100u16);
+
+        let sized = SizedNum::from(-5i8);
+        assert!(sized.resize(false, 8).is_none());
+        assert!(sized.resize(true, 3).is_none());
+        let ret: i8 = sized.resize(true, 4).unwrap().into();
+        assert_eq!(ret, -5i8);
+        assert!(SizedNum::from(5u8).resize(true, 4).is_some());
+
+        let min = SizedNum::from(-128i16).resize(true, 8).unwrap();
+        assert_eq!(min.bits.len(), 8);
+        assert_eq!(min.to_i128(), Some(-128));
+        let ret: i8 = min.clone().into();
+        assert_eq!(ret, i8::MIN);
+        assert_eq!(min.resize(true, 12).unwrap().to_i128(), Some(-128));
+        assert!(SizedNum::from(-129i16).resize(true, 8).is_none());
+        assert!(SizedNum::from(128u8).resize(true, 8).is_none());
+        let min: i16 = SizedNum::from(-2048i16).resize(true, 12).unwrap().into();
+        assert_eq!(min, -2048);
+    }
+
+    #[test]
+    fn i128() {
+        for n in &[0, 1, -1, 255, -256, i128::MAX, i128::MIN + 1] {
+            assert_eq!(SizedNum::from_i128(*n).unwrap().to_i128(), Some(*n));
+        }
+        assert_eq!(SizedNum::from_i128(200).unwrap(), SizedNum::from(200u8));
+        assert!(SizedNum::from_i128(i128::MIN).is_none());
+        assert_eq!(SizedNum::from(u128::MAX).to_i128(), None);
+    }
 }
-=======
-use std::collections::VecDeque;
-use std::convert::TryInto;
-use std::fmt::Debug;
-use std::fmt::Display;
-use std::str::FromStr;
-
-#[derive(Clone, Hash, PartialEq, Eq)]
-pub struct SizedNum {
-    pub signed: bool,
-    pub bits: Vec<bool>,
-}
-
-impl FromStr for SizedNum {
-    type Err = std::num::ParseIntError;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let num: u128 = s.parse()?;
-        Ok(num.into())
-    }
-}
-
-impl SizedNum {
-    pub fn zero(signed: bool, size: u8) -> Self {
-        if size < 1 || size > 128 {
-            panic!("Tried to make SizedNum with size = {}", size)
-        }
-        Self {
-            signed,
-            bits: (0..size).map(|_| false).collect(),
-        }
-    }
-
-    pub fn from_bits(signed: bool, bits: Vec<bool>) -> Self {
-        if bits.is_empty() || bits.len() > 128 {
-            panic!("Tried to make SizedNum with size = {}", bits.len())
-        }
-        SizedNum { signed, bits }
-    }
-
-    pub fn is_neg(&self) -> bool {
-        self.signed && self.bits[0]
-    }
-}
-
-impl Debug for SizedNum {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let bits = {
-            let mut res = String::new();
-            for bit in &self.bits {
-                res += if *bit { "1" } else { "0" };
-            }
-            res
-        };
-        f.debug_struct("SizedNum")
-            .field("signed", &self.signed)
-            .field("bits", &bits)
-            .finish()
-    }
-}
-
-impl Display for SizedNum {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(
-            f,
-            "{}{}{}",
-            Number::from(self.clone()),
-            if self.signed { "i" } else { "u" },
-            self.bits.len()
-        )
-    }
-}
-
-macro_rules! conv_sized {
-    ($t:ty) => {
-        impl From<$t> for SizedNum {
-            fn from(mut n: $t) -> Self {
-                let mut bits = VecDeque::new();
-                while n > 0 {
-                    bits.push_front(n % 2 == 1);
-                    n /= 2;
-                }
-                Self::from_bits(false, bits.into_iter().collect::<Vec<_>>())
-            }
-        }
-        impl Into<$t> for SizedNum {
-            fn into(self) -> $t {
-                Number::from(self).try_into().unwrap()
-            }
-        }
-    };
-    ($t:ty, signed) => {
-        impl From<$t> for SizedNum {
-            fn from(mut n: $t) -> Self {
-                let mut bits = VecDeque::new();
-                bits.push_back(n < 0);
-                n = n.abs();
-                while n > 0 {
-                    bits.insert(1, n % 2 == 1);
-                    n /= 2;
-                }
-                Self::from_bits(true, bits.into_iter().collect::<Vec<_>>())
-            }
-        }
-        impl Into<$t> for SizedNum {
-            fn into(self) -> $t {
-                Number::from(self).try_into().unwrap()
-            }
-        }
-    };
-}
-
-conv_sized!(u8);
-conv_sized!(u16);
-conv_sized!(u32);
-conv_sized!(u64);
-conv_sized!(u128);
-conv_sized!(i8, signed);
-conv_sized!(i16, signed);
-conv_sized!(i32, signed);
-conv_sized!(i64, signed);
-conv_sized!(i128, signed);
-
-impl Into<String> for SizedNum {
-    fn into(self) -> String {
-        format!(
-            "{}{}{}",
-            Number::from(self.clone()),
-            if self.signed { "u" } else { "i" },
-            self.bits.len()
-        )
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-pub enum Number {
-    U8(u8),
-    U16(u16),
-    U32(u32),
-    U64(u64),
-    U128(u128),
-    I8(i8),
-    I16(i16),
-    I32(i32),
-    I64(i64),
-    I128(i128),
-    F32(f32),
-    F64(f64),
-}
-
-impl Into<String> for Number {
-    fn into(self) -> String {
-        use Number::*;
-        match self {
-            U8(n) => format!("{}", n),
-            U16(n) => format!("{}", n),
-            U32(n) => format!("{}", n),
-            U64(n) => format!("{}", n),
-            U128(n) => format!("{}", n),
-            I8(n) => format!("{}", n),
-            I16(n) => format!("{}", n),
-            I32(n) => format!("{}", n),
-            I64(n) => format!("{}", n),
-            I128(n) => format!("{}", n),
-            F32(n) => format!("{}", n),
-            F64(n) => format!("{}", n),
-        }
-    }
-}
-
-impl Display for Number {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let s: String = (*self).into();
-        write!(f, "{}", s)
-    }
-}
-
-macro_rules! from_sized {
-    ($u:ty, $i:ty, $s:ident) => {
-        if $s.signed {
-            let mut res: $i = 0;
-            const TWO: $i = 2;
-            for i in 0..($s.bits.len() - 1) {
-                let bit = $s.bits[$s.bits.len() - i - 1];
-                if bit {
-                    res += TWO.pow(i as _);
-                }
-            }
-            if $s.is_neg() {
-                res = -res
-            }
-            Number::from(res)
-        } else {
-            let mut res: $u = 0;
-            const TWO: $u = 2;
-            for i in 0..$s.bits.len() {
-                let bit = $s.bits[$s.bits.len() - i - 1];
-                if bit {
-                    res += TWO.pow(i as _);
-                }
-            }
-            Number::from(res)
-        }
-    };
-}
-
-impl From<SizedNum> for Number {
-    #[allow(clippy::cognitive_complexity)]
-    fn from(n: SizedNum) -> Self {
-        match n.bits.len() {
-            1...8 => from_sized!(u8, i8, n),
-            9...16 => from_sized!(u16, i16, n),
-            17...32 => from_sized!(u32, i32, n),
-            33...64 => from_sized!(u64, i64, n),
-            65...128 => from_sized!(u128, i128, n),
-            _ => unreachable!(),
-        }
-    }
-}
-
-macro_rules! conv_num {
-    ($num:ty, $match:path) => {
-        impl TryInto<$num> for Number {
-            type Error = &'static str;
-            fn try_into(self) -> Result<$num, Self::Error> {
-                if let $match(n) = self {
-                    Ok(n)
-                } else {
-                    Err("Tried to convert Number enum to mismatched type")
-                }
-            }
-        }
-        impl From<$num> for Number {
-            fn from(n: $num) -> Self {
-                $match(n)
-            }
-        }
-    };
-}
-
-conv_num!(u8, Number::U8);
-conv_num!(u16, Number::U16);
-conv_num!(u32, Number::U32);
-conv_num!(u64, Number::U64);
-conv_num!(u128, Number::U128);
-conv_num!(i8, Number::I8);
-conv_num!(i16, Number::I16);
-conv_num!(i32, Number::I32);
-conv_num!(i64, Number::I64);
-conv_num!(i128, Number::I128);
-conv_num!(f32, Number::F32);
-conv_num!(f64, Number::F64);
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn sized() {
-        let sized = SizedNum::from(12u8);
-        dbg!(&sized);
-        let ret: u8 = sized.into();
-        assert_eq!(ret, 12u8);
-
-        let sized = SizedNum::from(-64i16);
-        dbg!(&sized);
-        eprintln!("{}", sized);
-        let ret: i8 = sized.into();
-        assert_eq!(ret, -64i8);
-
-        let sized = SizedNum::from(256i16);
-        dbg!(&sized);
-        eprintln!("{}", sized);
-        let ret: i16 = sized.into();
-        assert_eq!(ret, 256i16);
-    }
-}
->>>>>>> 98476a440fdd34cca12b2fea0108224ca3c2f6f2
@@ -1,2 +1,5 @@
+pub mod check;
+pub mod eval;
 pub mod lex;
 pub mod parse;
+pub mod resolve;
@@ -1,38 +1,37 @@
-<<<<<<< HEAD
 use crate::lex::number::SizedNum;
 use crate::lex::DelimToken;
 use crate::lex::{Token, TokenType};
+use crate::parse::types::StaticType;
 use birch::Tree;
 use either::Either;
 use std::collections::HashMap;
 use std::collections::HashSet;
 
 pub mod exprbuilder;
+pub mod grammar;
+pub mod selector;
+pub mod types;
 
-#[derive(Debug, Copy, Clone)]
-pub struct Selector {}
+pub use self::selector::Selector;
+use self::exprbuilder::{AstBlock, ExprBuilder, ExprStatus};
 
-impl Into<String> for Selector {
-    fn into(self) -> String {
-        unimplemented!()
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
+    Bool(bool),
     Str(String),
     Selector(Selector),
     Int(SizedNum),
     F32(f32),
-    F64(f32),
+    F64(f64),
 }
 
 impl Into<String> for Literal {
     fn into(self) -> String {
         use Literal::*;
         match self {
+            Bool(b) => b.to_string(),
             Str(s) => format!("\"{}\"", s),
-            Selector(s) => s.into(),
+            Selector(s) => format!("`{}`", s),
             Int(i) => i.into(),
             F32(f) => format!("{}f32", f),
             F64(f) => format!("{}f64", f),
@@ -40,28 +39,317 @@ impl Into<String> for Literal {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Byte offsets into the source a node was parsed from.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
+/// How a definition was declared.
+///
+/// `Super` marks a class declared as extending a parent (`class Child : super Parent { ... }`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum ObjType {
     Super,
     Type,
     Class,
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct ClassDef {}
+#[derive(Debug, Clone)]
+pub struct FieldDef<'s> {
+    pub name: String,
+    pub ty: StaticType,
+    pub default: Option<Node<'s>>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClassDef<'s> {
+    pub name: String,
+    /// Type parameters, as in `class Pair<A, B> { ... }`.
+    pub params: Vec<String>,
+    /// The class named after `super`, if any.
+    pub parent: Option<String>,
+    pub fields: Vec<FieldDef<'s>>,
+    /// Set for `type Name = Type;` aliases, which have no fields of their own.
+    pub alias: Option<StaticType>,
+    pub span: Span,
+}
+
+impl<'s> ClassDef<'s> {
+    pub fn new(name: String, span: Span) -> Self {
+        Self {
+            name,
+            params: Vec::new(),
+            parent: None,
+            fields: Vec::new(),
+            alias: None,
+            span,
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldDef<'s>> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Whether this is a tuple-like enum variant, whose fields are named by position.
+    pub fn is_tuple(&self) -> bool {
+        self.fields.first().is_some_and(|f| f.name == "0")
+    }
+}
+
+/// `name(param: Type = default, ...) = body`: a value with parameters, called like a function.
+#[derive(Debug, Clone)]
+pub struct TemplateDef<'s> {
+    pub name: String,
+    /// Parameters with no type are `Any`. Those with defaults come last.
+    pub params: Vec<FieldDef<'s>>,
+    pub body: Box<Node<'s>>,
+    pub span: Span,
+}
+
+impl<'s> TemplateDef<'s> {
+    /// The number of parameters without defaults, which every call has to pass.
+    pub fn required(&self) -> usize {
+        self.params.iter().filter(|p| p.default.is_none()).count()
+    }
+}
+
+/// How an entry of a `mod` override combines with the value it overrides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Strategy {
+    /// Dicts are merged key by key, and anything else is replaced. The default.
+    Deep,
+    /// `key @replace: value`
+    Replace,
+    /// `key @append: [...]` adds items to the end of a list.
+    Append,
+    /// `key @prepend: [...]` adds items to the start of a list.
+    Prepend,
+    /// `key @merge: [...]` or `key @merge(field): [...]` merges items of a list of dicts that
+    /// have the same value for `field`, `id` by default, and appends the rest.
+    MergeBy(String),
+}
+
+/// What a `use` brings into scope from the document it names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Import {
+    /// `use path;` or `use path as alias;`: the document itself, under its file name or alias.
+    Module(Option<String>),
+    /// `use path::{a, b as c};`: the named definitions, each with an optional new name.
+    Names(Vec<(String, Option<String>)>),
+    /// `use path::*;`: every definition the document exports.
+    Glob,
+}
+
+/// An infix operator, in an expression like `$base * 2`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+impl BinOp {
+    pub fn as_str(self) -> &'static str {
+        use BinOp::*;
+        match self {
+            Add => "+",
+            Sub => "-",
+            Mul => "*",
+            Div => "/",
+            Rem => "%",
+            Eq => "==",
+            Ne => "!=",
+            Lt => "<",
+            Le => "<=",
+            Gt => ">",
+            Ge => ">=",
+            And => "&&",
+            Or => "||",
+        }
+    }
+}
+
+/// A prefix operator: `-` or `!`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
+impl UnOp {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UnOp::Neg => "-",
+            UnOp::Not => "!",
+        }
+    }
+}
+
+/// `for x in items if cond` in a comprehension.
+#[derive(Debug, Clone)]
+pub struct Comprehension<'s> {
+    /// The name bound to each item, or to each index and item: `for i, x in list`. For a dict
+    /// these are each key, or each key and value.
+    pub vars: Vec<String>,
+    pub items: Box<Node<'s>>,
+    /// Only items for which this is true are kept.
+    pub filter: Option<Box<Node<'s>>>,
+}
 
 #[derive(Debug, Clone)]
 pub enum Ast<'s> {
-    Use(String, Option<String>),
+    Use(String, Import),
     Literal(Literal),
-    Def(ObjType, ClassDef),
-    Enum(ObjType, Vec<ClassDef>),
-    List(Option<String>, Vec<Ast<'s>>),
-    Map(Option<String>, HashMap<String, Ast<'s>>),
-    Tuple(Option<String>, Vec<Ast<'s>>),
+    /// A bare identifier used as a value.
+    Ident(String),
+    Index(String, Box<Node<'s>>),
+    Def(ObjType, ClassDef<'s>),
+    /// `enum Name { ... }`, with one `ClassDef` per variant.
+    Enum(ObjType, String, Vec<ClassDef<'s>>),
+    Template(TemplateDef<'s>),
+    List(Option<String>, Vec<Node<'s>>),
+    Map(Option<String>, HashMap<String, Node<'s>>),
+    Tuple(Option<String>, Vec<Node<'s>>),
+    /// `base < { ... }`
+    Mod(String, HashMap<String, Node<'s>>),
+    /// `$name` or `$name.field.0`: the value bound to `name`, then the path into it.
+    Ref(String, Vec<String>),
+    /// A string containing `${name}` references, as its literal parts and `Ast::Ref`s.
+    Interpolate(Vec<Node<'s>>),
+    /// `-key` in a dict, which removes `key` when the dict is applied as a `mod` override.
+    Remove,
+    /// `key @strategy: value` in a dict, which sets how `value` is applied as a `mod` override.
+    Merge(Strategy, Box<Node<'s>>),
+    Binary(BinOp, Box<Node<'s>>, Box<Node<'s>>),
+    Unary(UnOp, Box<Node<'s>>),
+    /// `value ~ f`: `value` passed as the first argument of `f`, a function's name, a call
+    /// missing its first argument, or a selector.
+    Pipe(Box<Node<'s>>, Box<Node<'s>>),
+    /// `if cond { then } else { otherwise }`
+    If(Box<Node<'s>>, Box<Node<'s>>, Box<Node<'s>>),
+    /// `[item for x in items]`
+    ListComp(Box<Node<'s>>, Comprehension<'s>),
+    /// `{ key: value for x in items }`
+    DictComp(Box<Node<'s>>, Box<Node<'s>>, Comprehension<'s>),
     Error(Vec<Token<'s>>),
 }
 
+#[derive(Debug, Clone)]
+pub struct Node<'s> {
+    pub span: Span,
+    pub ast: Ast<'s>,
+}
+
+impl<'s> Node<'s> {
+    pub fn new(span: Span, ast: Ast<'s>) -> Self {
+        Self { span, ast }
+    }
+
+    /// Calls `f` on this node and every node within it, parents before children.
+    pub fn visit_mut(&mut self, f: &mut impl FnMut(&mut Node<'s>)) {
+        f(self);
+        match &mut self.ast {
+            Ast::Index(_, inner) | Ast::Merge(_, inner) | Ast::Unary(_, inner) => {
+                inner.visit_mut(f)
+            }
+            Ast::Binary(_, left, right) | Ast::Pipe(left, right) => {
+                left.visit_mut(f);
+                right.visit_mut(f);
+            }
+            Ast::If(cond, then, otherwise) => {
+                cond.visit_mut(f);
+                then.visit_mut(f);
+                otherwise.visit_mut(f);
+            }
+            Ast::ListComp(item, comp) => {
+                item.visit_mut(f);
+                comp.visit_mut(f);
+            }
+            Ast::DictComp(key, value, comp) => {
+                key.visit_mut(f);
+                value.visit_mut(f);
+                comp.visit_mut(f);
+            }
+            Ast::List(_, items) | Ast::Tuple(_, items) | Ast::Interpolate(items) => {
+                for item in items {
+                    item.visit_mut(f);
+                }
+            }
+            Ast::Map(_, fields) | Ast::Mod(_, fields) => {
+                for val in fields.values_mut() {
+                    val.visit_mut(f);
+                }
+            }
+            Ast::Def(_, def) => {
+                for field in &mut def.fields {
+                    if let Some(default) = &mut field.default {
+                        default.visit_mut(f);
+                    }
+                }
+            }
+            Ast::Enum(_, _, variants) => {
+                for field in variants.iter_mut().flat_map(|v| &mut v.fields) {
+                    if let Some(default) = &mut field.default {
+                        default.visit_mut(f);
+                    }
+                }
+            }
+            Ast::Template(def) => {
+                for param in &mut def.params {
+                    if let Some(default) = &mut param.default {
+                        default.visit_mut(f);
+                    }
+                }
+                def.body.visit_mut(f);
+            }
+            _ => (),
+        }
+    }
+}
+
+impl<'s> Comprehension<'s> {
+    fn visit_mut(&mut self, f: &mut impl FnMut(&mut Node<'s>)) {
+        self.items.visit_mut(f);
+        if let Some(filter) = &mut self.filter {
+            filter.visit_mut(f);
+        }
+    }
+}
+
+impl<'s> Ast<'s> {
+    /// The name and arguments of a function call: a tuple named in lowercase, like `upper("x")`,
+    /// as opposed to a variant or class like `Some(1)` or `Shape::Circle(2.0)`.
+    pub fn call(&self) -> Option<(&str, &[Node<'s>])> {
+        match self {
+            Ast::Tuple(Some(name), args)
+                if name.starts_with(char::is_lowercase) && !name.contains("::") =>
+            {
+                Some((name, args))
+            }
+            _ => None,
+        }
+    }
+}
+
 impl<'s> From<Vec<Token<'s>>> for Ast<'s> {
     fn from(tokens: Vec<Token<'s>>) -> Self {
         Ast::Error(tokens)
@@ -107,8 +395,8 @@ impl<'s> Parser<'s> {
             };
             use ExprStatus::*;
             match result {
-                Ready(ex) => {
-                    self.ast.0.vert_mut(curr).val = AstBlock::Expr(ex);
+                Ready => {
+                    self.ast.0.vert_mut(curr).val = AstBlock::Expr(());
                     curr_stack.pop();
                     parent_stack.pop();
                 }
@@ -158,198 +446,3 @@ mod tests {
     //     eprintln!("{}", machine);
     // }
 }
-=======
-use crate::lex::number::SizedNum;
-use crate::lex::DelimToken;
-use crate::lex::{Token, TokenType};
-use birch::Tree;
-use either::Either;
-use std::collections::HashMap;
-use std::collections::HashSet;
-
-#[derive(Debug, Copy, Clone)]
-pub struct Selector {}
-
-impl Into<String> for Selector {
-    fn into(self) -> String {
-        unimplemented!()
-    }
-}
-
-#[derive(Debug, Clone)]
-pub enum Literal {
-    Str(String),
-    Selector(Selector),
-    Int(SizedNum),
-    F32(f32),
-    F64(f32),
-}
-
-impl Into<String> for Literal {
-    fn into(self) -> String {
-        use Literal::*;
-        match self {
-            Str(s) => format!("\"{}\"", s),
-            Selector(s) => s.into(),
-            Int(i) => i.into(),
-            F32(f) => format!("{}f32", f),
-            F64(f) => format!("{}f64", f),
-        }
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-pub enum ObjType {
-    Super,
-    Type,
-    Class,
-}
-
-#[derive(Debug, Copy, Clone)]
-pub struct ClassDef {}
-
-#[derive(Debug, Clone)]
-pub enum Ast<'s> {
-    Use(String, Option<String>),
-    Literal(Literal),
-    Def(ObjType, ClassDef),
-    Enum(ObjType, Vec<ClassDef>),
-    List(Option<String>, Vec<Ast<'s>>),
-    Map(Option<String>, HashMap<String, Ast<'s>>),
-    Tuple(Option<String>, Vec<Ast<'s>>),
-    Error(Vec<Token<'s>>),
-}
-
-impl<'s> From<Vec<Token<'s>>> for Ast<'s> {
-    fn from(tokens: Vec<Token<'s>>) -> Self {
-        Ast::Error(tokens)
-    }
-}
-
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
-pub enum ExprStatus {
-    Ready,
-    Incomplete,
-    Inner,
-    Error,
-}
-
-#[derive(Debug)]
-pub enum AstBlock<'s> {
-    Expr(()),
-    Builder(ExprBuilder<'s>),
-    Root,
-}
-
-#[derive(Debug, Default)]
-pub struct ExprBuilder<'s> {
-    pub tokens: Vec<Either<Token<'s>, usize>>,
-}
-
-impl<'s> ExprBuilder<'s> {
-    pub fn new() -> Self {
-        Self { tokens: Vec::new() }
-    }
-
-    pub fn push(&mut self, token: Token<'s>) -> ExprStatus {
-        use ExprStatus::*;
-        let ok = match self.tokens
-        Incomplete
-    }
-
-    pub fn push_inner(&mut self, inner: usize) {
-        self.tokens.push(Either::Right(inner))
-    }
-}
-
-pub struct Parser<'s> {
-    pub ast: Tree<AstBlock<'s>>,
-}
-
-impl Default for Parser<'_> {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-impl<'s> Parser<'s> {
-    pub fn new() -> Self {
-        Self {
-            ast: Tree::new(AstBlock::Root),
-        }
-    }
-
-    pub fn parse(
-        &mut self,
-        data: &'s [u8],
-    ) -> Result<&'s [u8], nom::Err<(&'s [u8], nom::error::ErrorKind)>> {
-        let (rem, mut tokens) = crate::lex::multilex(data)?;
-        let mut parent_stack = vec![0];
-        let mut curr_stack = Vec::new();
-        for token in tokens.drain(0..) {
-            let parent = parent_stack[parent_stack.len() - 1];
-            if curr_stack.is_empty() {
-                let curr = self
-                    .ast
-                    .add_child(parent, AstBlock::Builder(ExprBuilder::new()));
-                curr_stack.push(curr);
-            }
-            let curr = curr_stack[curr_stack.len() - 1];
-            let result = match self.ast.0.vert_mut(curr).val {
-                AstBlock::Builder(ref mut b) => b.push(token),
-                _ => unreachable!(), // Because we take completed builders off of curr_stack
-            };
-            use ExprStatus::*;
-            match result {
-                Complete(ex) => {
-                    self.ast.0.vert_mut(curr).val = AstBlock::Expr(ex);
-                    curr_stack.pop();
-                    parent_stack.pop();
-                }
-                Incomplete => (),
-                Error => panic!("Some sort of parse error."),
-                Inner => {
-                    parent_stack.push(curr);
-                    let parent = curr;
-                    let inner = self
-                        .ast
-                        .add_child(parent, AstBlock::Builder(ExprBuilder::new()));
-                    if let AstBlock::Builder(ref mut b) = self.ast.0.vert_mut(curr).val {
-                        b.push_inner(inner);
-                    }
-                    curr_stack.push(inner);
-                }
-            }
-        }
-        Ok(rem)
-    }
-
-    pub fn finish(self) -> Tree<()> {
-        unimplemented!()
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    // use crate::parse::machine::*;
-    const ABOUT: &[u8] = include_bytes!("../data/ashwalker.net/about.stn");
-
-    // #[test]
-    // fn parse() {
-    //     use Token::*;
-    //     let mut machine = ParseMachine::init(
-    //         |mut state, token| {
-    //             state.stack.push(token);
-    //             match token {
-    //                 Semi => Vec::new(),
-    //                 _ => vec![state],
-    //             }
-    //         },
-    //         true,
-    //     );
-    //     machine.parse(ABOUT);
-    //     eprintln!("{}", machine);
-    // }
-}
->>>>>>> 98476a440fdd34cca12b2fea0108224ca3c2f6f2
@@ -27,9 +27,8 @@ impl<'s> ExprBuilder<'s> {
     }
 
     pub fn push(&mut self, token: Token<'s>) -> ExprStatus {
-        use ExprStatus::*;
-        let ok = match self.tokens[self.tokens.len() - 1] {};
-        Incomplete
+        self.tokens.push(Either::Left(token));
+        ExprStatus::Incomplete
     }
 
     pub fn push_inner(&mut self, inner: usize) {
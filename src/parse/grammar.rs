@@ -0,0 +1,1023 @@
+use crate::lex::number::SizedNum;
+use crate::parse::selector::{Matcher, Op, Operand, Predicate, Selector, Step};
+use crate::parse::types::StaticType;
+use crate::parse::{
+    Ast, BinOp, ClassDef, Comprehension, FieldDef, Import, Literal, Node, ObjType, Span, Strategy,
+    TemplateDef, UnOp,
+};
+use pest::error::{Error, ErrorVariant};
+use pest::iterators::Pair;
+use pest::prec_climber::{Assoc, Operator, PrecClimber};
+use pest::Parser;
+use pest_derive::Parser;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Parser)]
+#[grammar = "stn.pest"]
+pub struct StnParser;
+
+/// Boxed, since pest's errors are large enough to bloat every `Result` they're returned in.
+pub type ParseError = Box<Error<Rule>>;
+
+/// A parsed `.stn` file.
+#[derive(Debug, Clone, Default)]
+pub struct Document<'s> {
+    /// `Ast::Use` nodes, in source order.
+    pub uses: Vec<Node<'s>>,
+    /// `Ast::Def`, `Ast::Enum` and `Ast::Template` nodes, in source order.
+    pub defs: Vec<Node<'s>>,
+    pub root: Option<Node<'s>>,
+    /// The names declared `pub`, which other documents may import. Everything else is private.
+    pub exports: HashSet<String>,
+}
+
+impl<'s> Document<'s> {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let stn = StnParser::parse(Rule::stn, input)?.next().unwrap();
+        let mut doc = Self::default();
+        for pair in stn.into_inner() {
+            let public = is_public(&pair);
+            match pair.as_rule() {
+                Rule::use_decl => doc.uses.push(build_use(pair)),
+                Rule::class_def | Rule::type_def | Rule::enum_def | Rule::template_def => {
+                    let def = match pair.as_rule() {
+                        Rule::enum_def => build_enum(pair)?,
+                        Rule::template_def => build_template(pair)?,
+                        _ => build_def(pair)?,
+                    };
+                    if public {
+                        doc.exports.extend(def_name(&def).map(String::from));
+                    }
+                    doc.defs.push(def);
+                }
+                Rule::named_dict | Rule::named_list => {
+                    let span = span(&pair);
+                    let mut inner = pair.into_inner().filter(|p| !is_keyword(p));
+                    let name = inner.next().unwrap().as_str().to_string();
+                    if public {
+                        doc.exports.insert(name.clone());
+                    }
+                    let mut map = HashMap::new();
+                    map.insert(name, build_obj(inner.next().unwrap())?);
+                    doc.root = Some(Node::new(span, Ast::Map(None, map)));
+                }
+                Rule::EOI => (),
+                _ => doc.root = Some(build_obj(pair)?),
+            }
+        }
+        Ok(doc)
+    }
+
+    pub fn class(&self, name: &str) -> Option<&ClassDef<'s>> {
+        self.defs.iter().find_map(|node| match &node.ast {
+            Ast::Def(_, def) if def.name == name => Some(def),
+            _ => None,
+        })
+    }
+
+    /// `name`'s class followed by each of its ancestors, stopping at a parent that isn't
+    /// declared or that's already been seen.
+    pub fn ancestors(&self, name: &str) -> Vec<&ClassDef<'s>> {
+        let mut chain: Vec<&ClassDef> = Vec::new();
+        let mut next = self.class(name);
+        while let Some(def) = next {
+            if chain.iter().any(|d| d.name == def.name) {
+                break;
+            }
+            chain.push(def);
+            next = def.parent.as_ref().and_then(|p| self.class(p));
+        }
+        chain
+    }
+
+    /// Whether `child` is `parent` or inherits from it.
+    pub fn is_subclass(&self, child: &str, parent: &str) -> bool {
+        self.ancestors(child).iter().any(|d| d.name == parent)
+    }
+
+    /// Every field of the class `name`, including those it inherits. Inherited fields come
+    /// first, and a field a subclass redeclares replaces its parent's.
+    pub fn fields(&self, name: &str) -> Vec<&FieldDef<'s>> {
+        let mut fields: Vec<&FieldDef> = Vec::new();
+        for def in self.ancestors(name).into_iter().rev() {
+            for field in &def.fields {
+                match fields.iter_mut().find(|f| f.name == field.name) {
+                    Some(inherited) => *inherited = field,
+                    None => fields.push(field),
+                }
+            }
+        }
+        fields
+    }
+
+    /// The names this document defines: its classes, types, enums and templates, then the keys
+    /// of a top-level dict.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.defs.iter().filter_map(def_name).collect();
+        if let Some(Node {
+            ast: Ast::Map(None, map),
+            ..
+        }) = &self.root
+        {
+            let mut keys: Vec<&str> = map.keys().map(|k| k.as_str()).collect();
+            keys.sort_unstable();
+            names.extend(keys);
+        }
+        names
+    }
+
+    /// Whether `name` was declared `pub`.
+    pub fn is_public(&self, name: &str) -> bool {
+        self.exports.contains(name)
+    }
+
+    /// The variants of the enum named `name`.
+    pub fn variants(&self, name: &str) -> Option<&[ClassDef<'s>]> {
+        self.defs.iter().find_map(|node| match &node.ast {
+            Ast::Enum(_, n, variants) if n == name => Some(&variants[..]),
+            _ => None,
+        })
+    }
+
+    /// The parameterised definition named `name`.
+    pub fn template(&self, name: &str) -> Option<&TemplateDef<'s>> {
+        self.defs.iter().find_map(|node| match &node.ast {
+            Ast::Template(def) if def.name == name => Some(def),
+            _ => None,
+        })
+    }
+}
+
+fn def_name<'a>(node: &'a Node) -> Option<&'a str> {
+    match &node.ast {
+        Ast::Def(_, def) => Some(&def.name),
+        Ast::Enum(_, name, _) => Some(name),
+        Ast::Template(def) => Some(&def.name),
+        _ => None,
+    }
+}
+
+/// Whether a declaration starts with `pub`.
+fn is_public(pair: &Pair<Rule>) -> bool {
+    pair.clone()
+        .into_inner()
+        .next()
+        .is_some_and(|p| p.as_rule() == Rule::vis)
+}
+
+fn span(pair: &Pair<Rule>) -> Span {
+    let span = pair.as_span();
+    Span::new(span.start(), span.end())
+}
+
+fn error(pair: &Pair<Rule>, message: impl Into<String>) -> ParseError {
+    Box::new(Error::new_from_span(
+        ErrorVariant::CustomError {
+            message: message.into(),
+        },
+        pair.as_span(),
+    ))
+}
+
+fn build_use<'s>(pair: Pair<Rule>) -> Node<'s> {
+    let span = span(&pair);
+    let mut inner = pair.into_inner().filter(|p| !is_keyword(p));
+    let url = inner.next().unwrap().as_str().to_string();
+    let import = match inner.next() {
+        None => Import::Module(None),
+        Some(pair) => match pair.as_rule() {
+            Rule::glob => Import::Glob,
+            Rule::use_items => Import::Names(
+                pair.into_inner()
+                    .map(|item| {
+                        let mut inner = item.into_inner();
+                        let name = inner.next().unwrap().as_str().to_string();
+                        (name, inner.next().map(|a| a.as_str().to_string()))
+                    })
+                    .collect(),
+            ),
+            _ => Import::Module(Some(pair.as_str().to_string())),
+        },
+    };
+    Node::new(span, Ast::Use(url, import))
+}
+
+fn build_type(pair: Pair<Rule>) -> Result<StaticType, ParseError> {
+    let mut inner = pair.clone().into_inner();
+    let name = inner.next().unwrap().as_str();
+    let args = inner.map(build_type).collect::<Result<_, _>>()?;
+    StaticType::generic(name, args).map_err(|e| error(&pair, e))
+}
+
+fn build_fields<'s>(pair: Pair<Rule>) -> Result<Vec<FieldDef<'s>>, ParseError> {
+    let mut fields: Vec<FieldDef> = Vec::new();
+    for field in pair.into_inner() {
+        let span = span(&field);
+        let mut inner = field.into_inner();
+        let name = inner.next().unwrap();
+        if fields.iter().any(|f| f.name == name.as_str()) {
+            return Err(error(&name, format!("duplicate field `{}`", name.as_str())));
+        }
+        let ty = build_type(inner.next().unwrap())?;
+        let default = match inner.next() {
+            Some(val) => Some(build_obj(val)?),
+            None => None,
+        };
+        fields.push(FieldDef {
+            name: name.as_str().to_string(),
+            ty,
+            default,
+            span,
+        });
+    }
+    Ok(fields)
+}
+
+fn build_def<'s>(pair: Pair<Rule>) -> Result<Node<'s>, ParseError> {
+    let span = span(&pair);
+    let mut obj_type = match pair.as_rule() {
+        Rule::class_def => ObjType::Class,
+        _ => ObjType::Type,
+    };
+    let mut inner = pair.into_inner().filter(|p| !is_keyword(p));
+    let mut def = ClassDef::new(inner.next().unwrap().as_str().to_string(), span);
+    for part in inner {
+        match part.as_rule() {
+            Rule::type_params => {
+                def.params = part.into_inner().map(|p| p.as_str().to_string()).collect()
+            }
+            Rule::super_class => {
+                obj_type = ObjType::Super;
+                def.parent = Some(part.into_inner().next().unwrap().as_str().to_string());
+            }
+            Rule::fields => def.fields = build_fields(part)?,
+            Rule::type_expr => def.alias = Some(build_type(part)?),
+            _ => unreachable!(),
+        }
+    }
+    Ok(Node::new(span, Ast::Def(obj_type, def)))
+}
+
+fn build_enum<'s>(pair: Pair<Rule>) -> Result<Node<'s>, ParseError> {
+    let span = span(&pair);
+    let mut inner = pair.into_inner().filter(|p| !is_keyword(p));
+    let name = inner.next().unwrap().as_str().to_string();
+    let mut variants: Vec<ClassDef> = Vec::new();
+    for variant in inner {
+        let v_span = self::span(&variant);
+        let mut parts = variant.into_inner();
+        let v_name = parts.next().unwrap();
+        if variants.iter().any(|v| v.name == v_name.as_str()) {
+            return Err(error(
+                &v_name,
+                format!("duplicate variant `{}`", v_name.as_str()),
+            ));
+        }
+        let mut def = ClassDef::new(v_name.as_str().to_string(), v_span);
+        match parts.next() {
+            Some(p) if p.as_rule() == Rule::tuple_fields => {
+                for (i, ty) in p.into_inner().enumerate() {
+                    def.fields.push(FieldDef {
+                        name: i.to_string(),
+                        span: self::span(&ty),
+                        ty: build_type(ty)?,
+                        default: None,
+                    });
+                }
+            }
+            Some(p) => def.fields = build_fields(p)?,
+            None => (),
+        }
+        variants.push(def);
+    }
+    Ok(Node::new(span, Ast::Enum(ObjType::Type, name, variants)))
+}
+
+fn build_template<'s>(pair: Pair<Rule>) -> Result<Node<'s>, ParseError> {
+    let span = span(&pair);
+    let mut inner = pair.into_inner().filter(|p| !is_keyword(p));
+    let name = inner.next().unwrap();
+    // Only a lowercase name is called like a function; `Name(...)` is a class or variant.
+    if !name.as_str().starts_with(char::is_lowercase) {
+        return Err(error(
+            &name,
+            "a template's name must start with a lowercase letter",
+        ));
+    }
+    let name = name.as_str().to_string();
+    let mut params: Vec<FieldDef> = Vec::new();
+    for param in inner.next().unwrap().into_inner() {
+        let p_span = self::span(&param);
+        let mut parts = param.clone().into_inner();
+        let p_name = parts.next().unwrap();
+        if params.iter().any(|p| p.name == p_name.as_str()) {
+            return Err(error(
+                &p_name,
+                format!("duplicate parameter `{}`", p_name.as_str()),
+            ));
+        }
+        let mut ty = StaticType::Any;
+        let mut default = None;
+        for part in parts {
+            match part.as_rule() {
+                Rule::type_expr => ty = build_type(part)?,
+                _ => default = Some(build_obj(part)?),
+            }
+        }
+        if default.is_none() && params.iter().any(|p| p.default.is_some()) {
+            return Err(error(
+                &param,
+                "parameters without defaults must come before those with them",
+            ));
+        }
+        params.push(FieldDef {
+            name: p_name.as_str().to_string(),
+            ty,
+            default,
+            span: p_span,
+        });
+    }
+    let body = Box::new(build_obj(inner.next().unwrap())?);
+    Ok(Node::new(
+        span,
+        Ast::Template(TemplateDef {
+            name,
+            params,
+            body,
+            span,
+        }),
+    ))
+}
+
+fn build_number(pair: &Pair<Rule>) -> Result<Literal, ParseError> {
+    let s = pair.as_str();
+    if s.contains(&['.', 'e', 'E'][..]) {
+        return s
+            .parse()
+            .map(Literal::F64)
+            .map_err(|e| error(pair, format!("{}", e)));
+    }
+    let int = if s.starts_with('-') {
+        match s.parse::<i128>() {
+            // A `SizedNum` keeps the magnitude in 127 bits, which `i128::MIN`'s doesn't fit.
+            Ok(i128::MIN) => return Err(error(pair, "integer literal is out of range")),
+            n => n.map(SizedNum::from),
+        }
+    } else {
+        s.parse::<u128>().map(|n| match n {
+            0 => SizedNum::zero(false, 1),
+            n => SizedNum::from(n),
+        })
+    };
+    int.map(Literal::Int)
+        .map_err(|e| error(pair, format!("{}", e)))
+}
+
+fn unescape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('b') => res.push('\u{8}'),
+            Some('f') => res.push('\u{c}'),
+            Some('n') => res.push('\n'),
+            Some('r') => res.push('\r'),
+            Some('t') => res.push('\t'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                // The grammar only accepts four hex digits, but they may still be a lone surrogate.
+                let c = u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                    .unwrap_or(std::char::REPLACEMENT_CHARACTER);
+                res.push(c);
+            }
+            Some(c) => res.push(c),
+            None => (),
+        }
+    }
+    res
+}
+
+fn build_index(pair: &Pair<Rule>) -> Result<isize, ParseError> {
+    pair.as_str()
+        .parse()
+        .map_err(|e| error(pair, format!("{}", e)))
+}
+
+pub(crate) fn build_selector(pair: Pair<Rule>) -> Result<Selector, ParseError> {
+    let mut steps = Vec::new();
+    for step in pair.into_inner() {
+        let mut descendant = false;
+        for part in step.into_inner() {
+            let matcher = match part.as_rule() {
+                Rule::sel_desc => {
+                    descendant = true;
+                    continue;
+                }
+                Rule::ident => Matcher::Field(part.as_str().to_string()),
+                Rule::sel_wild => Matcher::Wildcard,
+                Rule::sel_index => Matcher::Index(build_index(&part)?),
+                Rule::sel_range => {
+                    let dots = part.as_str().find("..").unwrap() + part.as_span().start();
+                    let (mut start, mut end) = (None, None);
+                    for bound in part.into_inner() {
+                        if bound.as_span().start() < dots {
+                            start = Some(build_index(&bound)?);
+                        } else {
+                            end = Some(build_index(&bound)?);
+                        }
+                    }
+                    Matcher::Range(start, end)
+                }
+                Rule::sel_pred => {
+                    let mut inner = part.into_inner();
+                    let field = inner.next().unwrap().as_str().to_string();
+                    let test = match (inner.next(), inner.next()) {
+                        (Some(op), Some(value)) => {
+                            let op = match op.as_str() {
+                                "=" => Op::Eq,
+                                "!=" => Op::Ne,
+                                "<" => Op::Lt,
+                                "<=" => Op::Le,
+                                ">" => Op::Gt,
+                                _ => Op::Ge,
+                            };
+                            let value = match value.as_rule() {
+                                Rule::ident => Operand::Ident(value.as_str().to_string()),
+                                Rule::string => Operand::Literal(Literal::Str(unescape(
+                                    value.into_inner().next().unwrap().as_str(),
+                                ))),
+                                Rule::boolean => {
+                                    Operand::Literal(Literal::Bool(value.as_str() == "true"))
+                                }
+                                _ => Operand::Literal(build_number(&value)?),
+                            };
+                            Some((op, value))
+                        }
+                        _ => None,
+                    };
+                    Matcher::Predicate(Predicate { field, test })
+                }
+                _ => unreachable!(),
+            };
+            steps.push(Step {
+                descendant,
+                matcher,
+            });
+        }
+    }
+    Ok(Selector { steps })
+}
+
+/// Splits `name.field.0` into the name and the path after it.
+fn build_ref<'s>(path: &str) -> Ast<'s> {
+    let mut parts = path.split('.').map(String::from);
+    let name = parts.next().unwrap();
+    Ast::Ref(name, parts.collect())
+}
+
+/// Builds a string literal, or an `Ast::Interpolate` if it contains `${name}` references.
+fn build_string<'s>(pair: Pair<Rule>) -> Result<Ast<'s>, ParseError> {
+    let raw = pair.as_str();
+    let start = pair.as_span().start();
+    let mut parts = Vec::new();
+    // The start of the literal text not yet added to `parts`.
+    let mut text = 0;
+    let mut chars = raw.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '$' if raw[i + 1..].starts_with('{') => {
+                let end = match raw[i..].find('}') {
+                    Some(end) => i + end,
+                    None => return Err(error(&pair, "unclosed `${` in string")),
+                };
+                let path = &raw[i + 2..end];
+                let valid = StnParser::parse(Rule::ref_path, path)
+                    .is_ok_and(|mut p| p.next().unwrap().as_str() == path);
+                if !valid {
+                    return Err(error(&pair, format!("`{}` isn't a reference", path)));
+                }
+                if text < i {
+                    let span = Span::new(start + text, start + i);
+                    let literal = Literal::Str(unescape(&raw[text..i]));
+                    parts.push(Node::new(span, Ast::Literal(literal)));
+                }
+                let span = Span::new(start + i, start + end + 1);
+                parts.push(Node::new(span, build_ref(path)));
+                text = end + 1;
+                while chars.peek().is_some_and(|&(j, _)| j < text) {
+                    chars.next();
+                }
+            }
+            _ => (),
+        }
+    }
+    if parts.is_empty() {
+        return Ok(Ast::Literal(Literal::Str(unescape(raw))));
+    }
+    if text < raw.len() {
+        let span = Span::new(start + text, start + raw.len());
+        let literal = Literal::Str(unescape(&raw[text..]));
+        parts.push(Node::new(span, Ast::Literal(literal)));
+    }
+    Ok(Ast::Interpolate(parts))
+}
+
+fn build_strategy(pair: Pair<Rule>) -> Result<Strategy, ParseError> {
+    let mut inner = pair.clone().into_inner();
+    let name = inner.next().unwrap().as_str();
+    let field = inner.next().map(|f| f.as_str().to_string());
+    match (name, field) {
+        ("replace", None) => Ok(Strategy::Replace),
+        ("append", None) => Ok(Strategy::Append),
+        ("prepend", None) => Ok(Strategy::Prepend),
+        ("merge", field) => Ok(Strategy::MergeBy(field.unwrap_or_else(|| "id".into()))),
+        _ => Err(error(
+            &pair,
+            format!("unknown merge strategy `{}`", pair.as_str()),
+        )),
+    }
+}
+
+fn build_dict<'s>(pair: Pair<Rule>) -> Result<HashMap<String, Node<'s>>, ParseError> {
+    let mut map = HashMap::new();
+    for entry in pair.into_inner() {
+        let span = span(&entry);
+        let mut inner = entry.into_inner();
+        let mut name = inner.next().unwrap();
+        let val = match inner.next() {
+            Some(strategy) if strategy.as_rule() == Rule::strategy => {
+                let strategy = build_strategy(strategy)?;
+                let val = build_obj(inner.next().unwrap())?;
+                Node::new(span, Ast::Merge(strategy, Box::new(val)))
+            }
+            Some(val) => build_obj(val)?,
+            None if name.as_rule() == Rule::remove => {
+                name = name.into_inner().next().unwrap();
+                Node::new(span, Ast::Remove)
+            }
+            None => Node::new(span, Ast::Ident(name.as_str().to_string())),
+        };
+        if map.insert(name.as_str().to_string(), val).is_some() {
+            return Err(error(&name, format!("duplicate key `{}`", name.as_str())));
+        }
+    }
+    Ok(map)
+}
+
+/// Operators from loosest to tightest binding, all left-associative.
+fn climber() -> PrecClimber<Rule> {
+    let op = |rule| Operator::new(rule, Assoc::Left);
+    PrecClimber::new(vec![
+        op(Rule::or),
+        op(Rule::and),
+        op(Rule::eq) | op(Rule::ne) | op(Rule::lt) | op(Rule::le) | op(Rule::gt) | op(Rule::ge),
+        op(Rule::pipe),
+        op(Rule::add) | op(Rule::sub),
+        op(Rule::mul) | op(Rule::div) | op(Rule::rem),
+    ])
+}
+
+fn build_expr<'s>(pair: Pair<Rule>) -> Result<Node<'s>, ParseError> {
+    climber().climb(pair.into_inner(), build_obj, |left, op, right| {
+        let (left, right) = (left?, right?);
+        let span = Span::new(left.span.start, right.span.end);
+        if op.as_rule() == Rule::pipe {
+            let callable = match &right.ast {
+                Ast::Ident(_) | Ast::Literal(Literal::Selector(_)) => true,
+                ast => ast.call().is_some(),
+            };
+            if !callable {
+                return Err(error(&op, "`~` must be followed by a function or selector"));
+            }
+            return Ok(Node::new(span, Ast::Pipe(Box::new(left), Box::new(right))));
+        }
+        let op = match op.as_rule() {
+            Rule::add => BinOp::Add,
+            Rule::sub => BinOp::Sub,
+            Rule::mul => BinOp::Mul,
+            Rule::div => BinOp::Div,
+            Rule::rem => BinOp::Rem,
+            Rule::eq => BinOp::Eq,
+            Rule::ne => BinOp::Ne,
+            Rule::lt => BinOp::Lt,
+            Rule::le => BinOp::Le,
+            Rule::gt => BinOp::Gt,
+            Rule::ge => BinOp::Ge,
+            Rule::and => BinOp::And,
+            Rule::or => BinOp::Or,
+            rule => unreachable!("Not an operator: {:?}", rule),
+        };
+        Ok(Node::new(
+            span,
+            Ast::Binary(op, Box::new(left), Box::new(right)),
+        ))
+    })
+}
+
+fn build_unary<'s>(pair: Pair<Rule>) -> Result<Node<'s>, ParseError> {
+    let end = pair.as_span().end();
+    let mut inner: Vec<_> = pair.into_inner().collect();
+    let mut node = build_obj(inner.pop().unwrap())?;
+    for prefix in inner.into_iter().rev() {
+        let op = match prefix.as_rule() {
+            Rule::neg => UnOp::Neg,
+            _ => UnOp::Not,
+        };
+        let span = Span::new(prefix.as_span().start(), end);
+        node = Node::new(span, Ast::Unary(op, Box::new(node)));
+    }
+    Ok(node)
+}
+
+/// Whether `pair` is a keyword, which only matters to the grammar.
+fn is_keyword(pair: &Pair<Rule>) -> bool {
+    matches!(
+        pair.as_rule(),
+        Rule::vis
+            | Rule::kw_use
+            | Rule::kw_class
+            | Rule::kw_type
+            | Rule::kw_enum
+            | Rule::kw_if
+            | Rule::kw_else
+            | Rule::kw_for
+            | Rule::kw_in
+    )
+}
+
+fn build_comprehension<'s>(pair: Pair<Rule>) -> Result<Comprehension<'s>, ParseError> {
+    let mut inner = pair.into_inner().filter(|p| !is_keyword(p));
+    let vars = inner
+        .next()
+        .unwrap()
+        .into_inner()
+        .map(|var| var.as_str().to_string())
+        .collect();
+    let items = Box::new(build_obj(inner.next().unwrap())?);
+    let filter = match inner.next() {
+        Some(filter) => Some(Box::new(build_obj(filter)?)),
+        None => None,
+    };
+    Ok(Comprehension {
+        vars,
+        items,
+        filter,
+    })
+}
+
+fn build_obj<'s>(pair: Pair<Rule>) -> Result<Node<'s>, ParseError> {
+    let span = span(&pair);
+    let ast = match pair.as_rule() {
+        Rule::expr => return build_expr(pair),
+        Rule::unary => return build_unary(pair),
+        Rule::boolean => Ast::Literal(Literal::Bool(pair.as_str() == "true")),
+        Rule::number | Rule::int => Ast::Literal(build_number(&pair)?),
+        Rule::string => build_string(pair.into_inner().next().unwrap())?,
+        Rule::selector_lit => Ast::Literal(Literal::Selector(build_selector(
+            pair.into_inner().next().unwrap(),
+        )?)),
+        Rule::reference => build_ref(pair.into_inner().next().unwrap().as_str()),
+        Rule::ident | Rule::variant_path => Ast::Ident(pair.as_str().to_string()),
+        Rule::index => {
+            let mut inner = pair.into_inner();
+            let name = inner.next().unwrap().as_str().to_string();
+            Ast::Index(name, Box::new(build_obj(inner.next().unwrap())?))
+        }
+        Rule::dict => Ast::Map(None, build_dict(pair)?),
+        Rule::if_expr => {
+            let mut inner = pair.into_inner().filter(|p| !is_keyword(p));
+            let cond = build_obj(inner.next().unwrap())?;
+            let then = build_obj(inner.next().unwrap())?;
+            let otherwise = build_obj(inner.next().unwrap())?;
+            Ast::If(Box::new(cond), Box::new(then), Box::new(otherwise))
+        }
+        Rule::list_comp => {
+            let mut inner = pair.into_inner();
+            let item = build_obj(inner.next().unwrap())?;
+            let comp = build_comprehension(inner.next().unwrap())?;
+            Ast::ListComp(Box::new(item), comp)
+        }
+        Rule::dict_comp => {
+            let mut inner = pair.into_inner();
+            let key = build_obj(inner.next().unwrap())?;
+            let value = build_obj(inner.next().unwrap())?;
+            let comp = build_comprehension(inner.next().unwrap())?;
+            Ast::DictComp(Box::new(key), Box::new(value), comp)
+        }
+        Rule::class => {
+            let mut inner = pair.into_inner();
+            let name = inner.next().unwrap().as_str().to_string();
+            Ast::Map(Some(name), build_dict(inner.next().unwrap())?)
+        }
+        Rule::mod_expr => {
+            let mut inner = pair.into_inner();
+            let name = inner.next().unwrap().as_str().to_string();
+            Ast::Mod(name, build_dict(inner.next().unwrap())?)
+        }
+        Rule::list => Ast::List(
+            None,
+            pair.into_inner().map(build_obj).collect::<Result<_, _>>()?,
+        ),
+        Rule::tuple => {
+            let mut name = None;
+            let mut items = Vec::new();
+            for item in pair.into_inner() {
+                if item.as_rule() == Rule::ident || item.as_rule() == Rule::variant_path {
+                    name = Some(item.as_str().to_string());
+                    continue;
+                }
+                let mut inner = item.into_inner();
+                let first = inner.next().unwrap();
+                if inner.next().is_some() {
+                    return Err(error(&first, "named tuple entries are not supported"));
+                }
+                items.push(build_obj(first)?);
+            }
+            Ast::Tuple(name, items)
+        }
+        rule => unreachable!("Not an object: {:?}", rule),
+    };
+    Ok(Node::new(span, ast))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn class_def() {
+        let doc = Document::parse(
+            r#"
+            class Button {
+                label: String,
+                width: u16 = 80,
+                icon: Icon,
+            }
+            class Toggle : super Button {
+                on: bool = false,
+            }
+            type Id = u32;
+            type Point = { x: f32, y: f32 }
+            Toggle { label: "Dark Mode" }
+            "#,
+        )
+        .unwrap();
+        let button = doc.class("Button").unwrap();
+        assert_eq!(button.fields.len(), 3);
+        assert_eq!(button.field("label").unwrap().ty, StaticType::Str);
+        assert_eq!(button.field("width").unwrap().ty, StaticType::Unsigned(16));
+        assert!(button.field("width").unwrap().default.is_some());
+        assert_eq!(
+            button.field("icon").unwrap().ty,
+            StaticType::Class("Icon".into(), Vec::new())
+        );
+        match &doc.defs[1].ast {
+            Ast::Def(ObjType::Super, def) => assert_eq!(def.parent.as_deref(), Some("Button")),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(doc.class("Id").unwrap().alias, Some(StaticType::Unsigned(32)));
+        assert_eq!(doc.class("Point").unwrap().fields.len(), 2);
+        match doc.root.unwrap().ast {
+            Ast::Map(Some(name), fields) => {
+                assert_eq!(name, "Toggle");
+                assert!(fields.contains_key("label"));
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn enum_def() {
+        let doc = Document::parse(
+            r#"
+            enum Shape { Empty, Circle(f32), Rect { w: f32, h: f32 } }
+            [Shape::Empty, Shape::Circle(2.0), Shape::Rect { w: 1.0, h: 3.0 }]
+            "#,
+        )
+        .unwrap();
+        let shapes = doc.variants("Shape").unwrap();
+        assert_eq!(shapes.len(), 3);
+        assert!(shapes[0].fields.is_empty());
+        assert!(shapes[1].is_tuple());
+        assert!(!shapes[2].is_tuple());
+        match doc.root.unwrap().ast {
+            Ast::List(None, items) => match &items[1].ast {
+                Ast::Tuple(Some(name), args) => {
+                    assert_eq!(name, "Shape::Circle");
+                    assert_eq!(args.len(), 1);
+                }
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn generics() {
+        let doc = Document::parse(
+            r#"
+            class Pair<A, B> { first: A, second: B }
+            type Table<T> = Map<String, List<T>>;
+            class Config { pins: List<Pair<String, u8>>, name: Option<String>, pos: Tuple<f32, f32> }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(doc.class("Pair").unwrap().params, vec!["A", "B"]);
+        let table: String = doc.class("Table").unwrap().alias.clone().unwrap().into();
+        assert_eq!(table, "Map<String, List<T>>");
+        let config = doc.class("Config").unwrap();
+        assert_eq!(
+            config.field("pins").unwrap().ty,
+            StaticType::List(Box::new(StaticType::Class(
+                "Pair".into(),
+                vec![StaticType::Str, StaticType::Unsigned(8)]
+            )))
+        );
+        assert!(Document::parse("class A { a: Map<String> }").is_err());
+        assert!(Document::parse("class A { a: u8<String> }").is_err());
+    }
+
+    #[test]
+    fn duplicate_field() {
+        assert!(Document::parse("class A { a: u8, a: u16 }").is_err());
+    }
+
+    #[test]
+    fn references() {
+        let doc = Document::parse(r#"{ a: $colors.red, b: "\${x} is ${name.0}!" }"#).unwrap();
+        let fields = match doc.root.unwrap().ast {
+            Ast::Map(None, fields) => fields,
+            other => panic!("{:?}", other),
+        };
+        match &fields["a"].ast {
+            Ast::Ref(name, path) => {
+                assert_eq!(name, "colors");
+                assert_eq!(path, &vec!["red".to_string()]);
+            }
+            other => panic!("{:?}", other),
+        }
+        match &fields["b"].ast {
+            Ast::Interpolate(parts) => match &parts[..] {
+                [Node {
+                    ast: Ast::Literal(Literal::Str(a)),
+                    ..
+                }, Node {
+                    ast: Ast::Ref(name, _),
+                    ..
+                }, Node {
+                    ast: Ast::Literal(Literal::Str(b)),
+                    ..
+                }] => assert_eq!(
+                    (a.as_str(), name.as_str(), b.as_str()),
+                    ("${x} is ", "name", "!")
+                ),
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+        assert!(Document::parse(r#"{ a: "${1x}" }"#).is_err());
+    }
+
+    #[test]
+    fn expressions() {
+        fn show(node: &Node) -> String {
+            match &node.ast {
+                Ast::Binary(op, l, r) => format!("({} {} {})", op.as_str(), show(l), show(r)),
+                Ast::Unary(op, x) => format!("({} {})", op.as_str(), show(x)),
+                Ast::Pipe(x, f) => format!("(~ {} {})", show(x), show(f)),
+                Ast::Tuple(Some(name), args) => {
+                    let args: Vec<_> = args.iter().map(show).collect();
+                    format!("{}({})", name, args.join(" "))
+                }
+                Ast::Literal(Literal::Int(n)) => {
+                    crate::lex::number::Number::from(n.clone()).to_string()
+                }
+                Ast::Ref(name, _) => format!("${}", name),
+                Ast::Ident(name) => name.clone(),
+                Ast::Tuple(None, items) => format!("{}-tuple", items.len()),
+                other => panic!("{:?}", other),
+            }
+        }
+        let doc = Document::parse(
+            "{ a: 1 + 2 * 3 - 4, b: -$x - -1, c: !(p || q) && r == s, d: (1,), e: (1), f: a < b,\
+             g: 1 + $x ~ f ~ g(1 + 2) < 3 }",
+        )
+        .unwrap();
+        let fields = match doc.root.unwrap().ast {
+            Ast::Map(None, fields) => fields,
+            other => panic!("{:?}", other),
+        };
+        let show = |key| show(&fields[key]);
+        assert_eq!(show("a"), "(- (+ 1 (* 2 3)) 4)");
+        assert_eq!(show("b"), "(- (- $x) -1)");
+        assert_eq!(show("c"), "(&& (! (|| p q)) (== r s))");
+        assert_eq!(show("d"), "1-tuple");
+        assert_eq!(show("e"), "1");
+        assert_eq!(show("f"), "(< a b)");
+        assert_eq!(fields["a"].span, Span::new(5, 18));
+
+        assert_eq!(show("g"), "(< (~ (~ (+ 1 $x) f) g((+ 1 2))) 3)");
+        assert!(Document::parse("{ a: $x ~ 2 }").is_err());
+    }
+
+    #[test]
+    fn comprehensions() {
+        let doc = Document::parse(
+            r#"{
+                a: if $x > 1 { "big" } else if (flag) { "flag" } else { "small" },
+                b: [x * 2 for x in $items if x > 0],
+                c: { k: v for k, v in $d },
+                iffy: [for_, in_],
+            }"#,
+        )
+        .unwrap();
+        let fields = match doc.root.unwrap().ast {
+            Ast::Map(None, fields) => fields,
+            other => panic!("{:?}", other),
+        };
+        match &fields["a"].ast {
+            Ast::If(cond, _, otherwise) => {
+                assert!(matches!(cond.ast, Ast::Binary(BinOp::Gt, ..)));
+                assert!(matches!(otherwise.ast, Ast::If(..)));
+            }
+            other => panic!("{:?}", other),
+        }
+        match &fields["b"].ast {
+            Ast::ListComp(item, comp) => {
+                assert!(matches!(item.ast, Ast::Binary(BinOp::Mul, ..)));
+                assert_eq!(comp.vars, vec!["x"]);
+                assert!(matches!(comp.items.ast, Ast::Ref(..)));
+                assert!(comp.filter.is_some());
+            }
+            other => panic!("{:?}", other),
+        }
+        match &fields["c"].ast {
+            Ast::DictComp(_, _, comp) => {
+                assert_eq!(comp.vars, vec!["k", "v"]);
+                assert!(comp.filter.is_none());
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(fields["iffy"].ast, Ast::List(None, _)));
+        assert!(Document::parse("{ a: if $x { 1 } }").is_err());
+        assert!(Document::parse("{ a: [x for x, y, z in $d] }").is_err());
+    }
+
+    #[test]
+    fn templates() {
+        let doc = Document::parse(
+            r#"
+            class Card { title: String, body: String }
+            pub card(title: String, body = "") = Card { title: $title, body: $body };
+            pair(a, b) = [$a, $b]
+            { a: card("x"), b: pair(1, 2) }
+            "#,
+        )
+        .unwrap();
+        let card = doc.template("card").unwrap();
+        assert_eq!(card.params.len(), 2);
+        assert_eq!(card.params[0].ty, StaticType::Str);
+        assert_eq!(card.params[1].ty, StaticType::Any);
+        assert_eq!(card.required(), 1);
+        assert!(matches!(card.body.ast, Ast::Map(Some(_), _)));
+        assert!(doc.is_public("card"));
+        assert_eq!(doc.names(), vec!["Card", "card", "pair", "a", "b"]);
+
+        assert!(Document::parse("f(a, a) = 1").is_err());
+        assert!(Document::parse("f(a = 1, b) = 1").is_err());
+        assert!(Document::parse("F(a) = 1").is_err());
+    }
+
+    #[test]
+    fn int_literals() {
+        let doc = Document::parse(
+            "[-170141183460469231731687303715884105727, 340282366920938463463374607431768211455]",
+        );
+        assert!(doc.is_ok());
+        assert!(Document::parse("[-170141183460469231731687303715884105728]").is_err());
+        assert!(Document::parse("[340282366920938463463374607431768211456]").is_err());
+    }
+
+    #[test]
+    fn keyword_prefixes() {
+        let doc = Document::parse("classes { a: u8 }").unwrap();
+        assert!(doc.defs.is_empty());
+        assert!(matches!(doc.root.unwrap().ast, Ast::Map(Some(name), _) if name == "classes"));
+        let doc = Document::parse("enums { A }").unwrap();
+        assert!(doc.defs.is_empty());
+        // Not `use r.stn;`.
+        assert!(Document::parse("user.stn;").is_err());
+        let doc = Document::parse("use lib.stn;\ntyped(x) = $x\n{ a: typed(1) }").unwrap();
+        assert_eq!(doc.uses.len(), 1);
+        assert!(doc.template("typed").is_some());
+    }
+}
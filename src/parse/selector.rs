@@ -0,0 +1,497 @@
+use crate::lex::number::Number;
+use crate::parse::grammar::{build_selector, ParseError, Rule, StnParser};
+use crate::parse::{Ast, Literal};
+use pest::Parser;
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// A path to parts of a document, written between backticks: `` `servers[*].port` ``.
+///
+/// Each step is `.name` for a dict key, `.*` or `[*]` for every entry, `[2]` for a list item
+/// (negative indexes count from the end), `[1..3]` for a range of items, or `[key]` and
+/// `[key op value]` for the entries whose `key` field exists or compares with `value`. `..`
+/// before a step matches it at any depth. The first step needs no leading `.`, and an empty
+/// selector matches the whole document.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Selector {
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// Set for `..`, which matches the step at any depth rather than only directly below.
+    pub descendant: bool,
+    pub matcher: Matcher,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Matcher {
+    Field(String),
+    Wildcard,
+    Index(isize),
+    /// `[start..end]`, excluding `end`. Either bound may be left out.
+    Range(Option<isize>, Option<isize>),
+    Predicate(Predicate),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Predicate {
+    pub field: String,
+    /// The comparison, or `None` if the field only has to exist.
+    pub test: Option<(Op, Operand)>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        }
+    }
+}
+
+/// The value a predicate compares a field with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Literal(Literal),
+    /// A bare identifier, such as an enum variant.
+    Ident(String),
+}
+
+/// One step of the path from a document's root to a value within it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Field(String),
+    Item(usize),
+}
+
+/// The value at `path` in `root`.
+pub fn get<'a, 's>(root: &'a Ast<'s>, path: &[Key]) -> Option<&'a Ast<'s>> {
+    path.iter().try_fold(root, |ast, key| match (ast, key) {
+        (Ast::Map(_, fields), Key::Field(name)) | (Ast::Mod(_, fields), Key::Field(name)) => {
+            fields.get(name).map(|n| &n.ast)
+        }
+        (Ast::List(_, items), Key::Item(i)) | (Ast::Tuple(_, items), Key::Item(i)) => {
+            items.get(*i).map(|n| &n.ast)
+        }
+        _ => None,
+    })
+}
+
+pub fn get_mut<'a, 's>(root: &'a mut Ast<'s>, path: &[Key]) -> Option<&'a mut Ast<'s>> {
+    path.iter().try_fold(root, |ast, key| match (ast, key) {
+        (Ast::Map(_, fields), Key::Field(name)) | (Ast::Mod(_, fields), Key::Field(name)) => {
+            fields.get_mut(name).map(|n| &mut n.ast)
+        }
+        (Ast::List(_, items), Key::Item(i)) | (Ast::Tuple(_, items), Key::Item(i)) => {
+            items.get_mut(*i).map(|n| &mut n.ast)
+        }
+        _ => None,
+    })
+}
+
+/// The entries of a dict, sorted by key, or the items of a list or tuple.
+fn children<'a, 's>(ast: &'a Ast<'s>) -> Vec<(Key, &'a Ast<'s>)> {
+    match ast {
+        Ast::Map(_, fields) | Ast::Mod(_, fields) => {
+            let mut children: Vec<_> = fields
+                .iter()
+                .map(|(k, v)| (Key::Field(k.clone()), &v.ast))
+                .collect();
+            children.sort_by(|a, b| match (&a.0, &b.0) {
+                (Key::Field(a), Key::Field(b)) => a.cmp(b),
+                _ => Ordering::Equal,
+            });
+            children
+        }
+        Ast::List(_, items) | Ast::Tuple(_, items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (Key::Item(i), &item.ast))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Turns a possibly negative index into an offset from the start, clamped to `0..=len`.
+fn offset(index: isize, len: usize) -> usize {
+    if index < 0 {
+        len.saturating_sub(index.unsigned_abs())
+    } else {
+        (index as usize).min(len)
+    }
+}
+
+impl Matcher {
+    /// The children of `ast` this matcher selects.
+    fn children<'a, 's>(&self, ast: &'a Ast<'s>) -> Vec<(Key, &'a Ast<'s>)> {
+        let items = match ast {
+            Ast::List(_, items) | Ast::Tuple(_, items) => Some(items),
+            _ => None,
+        };
+        match (self, items) {
+            (Matcher::Field(name), _) => match ast {
+                Ast::Map(_, fields) | Ast::Mod(_, fields) => fields
+                    .get(name)
+                    .map(|n| (Key::Field(name.clone()), &n.ast))
+                    .into_iter()
+                    .collect(),
+                _ => Vec::new(),
+            },
+            (Matcher::Wildcard, _) => children(ast),
+            (Matcher::Index(i), Some(items)) => {
+                let i = if *i < 0 {
+                    items.len().checked_sub(i.unsigned_abs())
+                } else {
+                    Some(*i as usize)
+                };
+                i.and_then(|i| items.get(i).map(|n| (Key::Item(i), &n.ast)))
+                    .into_iter()
+                    .collect()
+            }
+            (Matcher::Range(start, end), Some(items)) => {
+                let start = start.map_or(0, |s| offset(s, items.len()));
+                let end = end.map_or(items.len(), |e| offset(e, items.len()));
+                (start..end.max(start))
+                    .map(|i| (Key::Item(i), &items[i].ast))
+                    .collect()
+            }
+            (Matcher::Predicate(p), _) => children(ast)
+                .into_iter()
+                .filter(|(_, child)| p.test(child))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Predicate {
+    /// Whether `ast` is a dict or `mod` override whose field passes this predicate.
+    pub fn test(&self, ast: &Ast) -> bool {
+        let field = match ast {
+            Ast::Map(_, fields) | Ast::Mod(_, fields) => match fields.get(&self.field) {
+                Some(field) => &field.ast,
+                None => return false,
+            },
+            _ => return false,
+        };
+        let (op, operand) = match &self.test {
+            Some(test) => test,
+            None => return true,
+        };
+        let ordering = compare(field, operand);
+        match op {
+            Op::Eq => ordering == Some(Ordering::Equal),
+            Op::Ne => ordering != Some(Ordering::Equal),
+            Op::Lt => ordering == Some(Ordering::Less),
+            Op::Le => matches!(ordering, Some(Ordering::Less) | Some(Ordering::Equal)),
+            Op::Gt => ordering == Some(Ordering::Greater),
+            Op::Ge => matches!(ordering, Some(Ordering::Greater) | Some(Ordering::Equal)),
+        }
+    }
+}
+
+/// Compares a value with a predicate's operand, or `None` if they can't be compared.
+///
+/// Identifiers match by name, so `Circle` matches `Shape::Circle` and instances of `Circle`.
+fn compare(ast: &Ast, operand: &Operand) -> Option<Ordering> {
+    fn number(literal: &Literal) -> Option<f64> {
+        match literal {
+            Literal::Int(n) => Some(Number::from(n.clone()).to_f64()),
+            Literal::F32(n) => Some(f64::from(*n)),
+            Literal::F64(n) => Some(*n),
+            _ => None,
+        }
+    }
+    match (ast, operand) {
+        (Ast::Ident(name), Operand::Ident(expected))
+        | (Ast::Map(Some(name), _), Operand::Ident(expected))
+        | (Ast::Tuple(Some(name), _), Operand::Ident(expected)) => {
+            let name = name.rsplit("::").next().unwrap_or(name);
+            let expected = expected.rsplit("::").next().unwrap_or(expected);
+            if name == expected {
+                Some(Ordering::Equal)
+            } else {
+                None
+            }
+        }
+        (Ast::Literal(Literal::Str(a)), Operand::Literal(Literal::Str(b))) => Some(a.cmp(b)),
+        (Ast::Literal(Literal::Bool(a)), Operand::Literal(Literal::Bool(b))) => Some(a.cmp(b)),
+        (Ast::Literal(a), Operand::Literal(b)) => number(a)?.partial_cmp(&number(b)?),
+        _ => None,
+    }
+}
+
+impl Selector {
+    /// The path to every value this selector matches in `root`, in document order.
+    pub fn paths(&self, root: &Ast) -> Vec<Vec<Key>> {
+        fn collect(steps: &[Step], ast: &Ast, path: &mut Vec<Key>, out: &mut Vec<Vec<Key>>) {
+            let step = match steps.first() {
+                Some(step) => step,
+                None => {
+                    if !out.contains(path) {
+                        out.push(path.clone());
+                    }
+                    return;
+                }
+            };
+            for (key, child) in step.matcher.children(ast) {
+                path.push(key);
+                collect(&steps[1..], child, path, out);
+                path.pop();
+            }
+            if step.descendant {
+                for (key, child) in children(ast) {
+                    path.push(key);
+                    collect(steps, child, path, out);
+                    path.pop();
+                }
+            }
+        }
+        let mut out = Vec::new();
+        collect(&self.steps, root, &mut Vec::new(), &mut out);
+        out
+    }
+
+    /// Every value this selector matches in `root`, in document order.
+    pub fn select<'a, 's>(&self, root: &'a Ast<'s>) -> Vec<&'a Ast<'s>> {
+        self.paths(root)
+            .iter()
+            .filter_map(|path| get(root, path))
+            .collect()
+    }
+
+    /// Calls `f` on every value this selector matches in `root`, in document order.
+    ///
+    /// Values are found before any are changed, so `f` can't affect which are matched.
+    pub fn select_mut<'s>(&self, root: &mut Ast<'s>, mut f: impl FnMut(&mut Ast<'s>)) {
+        for path in self.paths(root) {
+            if let Some(ast) = get_mut(root, &path) {
+                f(ast);
+            }
+        }
+    }
+
+    /// Every value this selector matches in each of `docs`, with the key of the document it was
+    /// found in.
+    pub fn select_all<'a, 's: 'a, K: Clone>(
+        &self,
+        docs: impl IntoIterator<Item = (K, &'a Ast<'s>)>,
+    ) -> Vec<(K, &'a Ast<'s>)> {
+        docs.into_iter()
+            .flat_map(|(key, root)| {
+                self.select(root)
+                    .into_iter()
+                    .map(move |ast| (key.clone(), ast))
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Selector {
+    type Err = ParseError;
+
+    /// Parses a selector without its backticks.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pairs = StnParser::parse(Rule::selector_full, s)?;
+        build_selector(pairs.next().unwrap().into_inner().next().unwrap())
+    }
+}
+
+fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' | '\\' | '$' => write!(f, "\\{}", c)?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Ident(name) => write!(f, "{}", name),
+            Operand::Literal(Literal::Str(s)) => write_str(f, s),
+            Operand::Literal(Literal::Bool(b)) => write!(f, "{}", b),
+            Operand::Literal(Literal::Int(n)) => write!(f, "{}", Number::from(n.clone())),
+            // `Debug` keeps the decimal point, so floats read back as floats.
+            Operand::Literal(Literal::F32(n)) => write!(f, "{:?}", n),
+            Operand::Literal(Literal::F64(n)) => write!(f, "{:?}", n),
+            Operand::Literal(Literal::Selector(s)) => write!(f, "`{}`", s),
+        }
+    }
+}
+
+impl Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, step) in self.steps.iter().enumerate() {
+            let bracketed = !matches!(step.matcher, Matcher::Field(_) | Matcher::Wildcard);
+            if step.descendant {
+                write!(f, "..")?;
+            } else if i > 0 && !bracketed {
+                write!(f, ".")?;
+            }
+            match &step.matcher {
+                Matcher::Field(name) => write!(f, "{}", name)?,
+                Matcher::Wildcard => write!(f, "*")?,
+                Matcher::Index(i) => write!(f, "[{}]", i)?,
+                Matcher::Range(start, end) => {
+                    write!(f, "[")?;
+                    if let Some(start) = start {
+                        write!(f, "{}", start)?;
+                    }
+                    write!(f, "..")?;
+                    if let Some(end) = end {
+                        write!(f, "{}", end)?;
+                    }
+                    write!(f, "]")?;
+                }
+                Matcher::Predicate(p) => {
+                    write!(f, "[{}", p.field)?;
+                    if let Some((op, value)) = &p.test {
+                        write!(f, " {} {}", op.as_str(), value)?;
+                    }
+                    write!(f, "]")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<Selector> for String {
+    fn from(selector: Selector) -> Self {
+        selector.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::number::SizedNum;
+    use crate::parse::grammar::Document;
+    use crate::parse::Ast;
+
+    #[test]
+    fn round_trip() {
+        for s in &[
+            "",
+            "servers",
+            "servers.*.port",
+            "items[0]",
+            "items[-1].name",
+            "items[1..3]",
+            "items[..2]",
+            "items[2..]",
+            "..button",
+            "pages..button[label = \"O\\\"K\"]",
+            "users[admin][age >= 18].name",
+            "shapes[kind != Circle][r < 2.0]",
+            "..[0]",
+        ] {
+            let selector: Selector = s.parse().unwrap();
+            assert_eq!(selector.to_string(), *s);
+        }
+        let selector: Selector = "a[*] . b[ x=1 ]".parse().unwrap();
+        assert_eq!(selector.to_string(), "a.*.b[x = 1]");
+        assert_eq!(
+            selector.steps[3].matcher,
+            Matcher::Predicate(Predicate {
+                field: "x".into(),
+                test: Some((
+                    Op::Eq,
+                    Operand::Literal(Literal::Int(SizedNum::from(1u128)))
+                )),
+            })
+        );
+        assert!("a.[0]".parse::<Selector>().is_err());
+        assert!("a[1..2..3]".parse::<Selector>().is_err());
+
+        let doc = Document::parse("{ target: `pages[*]..button` }").unwrap();
+        match doc.root.unwrap().ast {
+            Ast::Map(_, fields) => match &fields["target"].ast {
+                Ast::Literal(Literal::Selector(s)) => assert_eq!(s.steps.len(), 3),
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn select() {
+        let doc = Document::parse(
+            r#"{
+                servers: { a: { port: 80, up: true }, b: { port: 8080, up: false } },
+                items: [{ name: "x", kind: Small }, { name: "y", kind: Large }, { name: "z" }],
+                pages: [{ button: { label: "ok" }, body: { button: { label: "go" } } }],
+                mods: [base < { port: 80 }, base < { port: 9 }],
+            }"#,
+        )
+        .unwrap();
+        let root = doc.root.unwrap().ast;
+        fn text(ast: &Ast) -> String {
+            match ast {
+                Ast::Literal(l) => l.clone().into(),
+                other => format!("{:?}", other),
+            }
+        }
+        let select = |s: &str| {
+            let selector: Selector = s.parse().unwrap();
+            selector
+                .select(&root)
+                .into_iter()
+                .map(text)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(select("servers.*.port"), ["80i7", "8080i13"]);
+        assert_eq!(select("servers[port > 100].port"), ["8080i13"]);
+        assert_eq!(select("servers[up = false].port"), ["8080i13"]);
+        assert_eq!(select("items[-1].name"), ["\"z\""]);
+        assert_eq!(select("items[1..].name"), ["\"y\"", "\"z\""]);
+        assert_eq!(select("items[..-2].name"), ["\"x\""]);
+        assert_eq!(select("items[kind = Large].name"), ["\"y\""]);
+        assert_eq!(select("items[kind != Large].name"), ["\"x\""]);
+        assert_eq!(select("items[kind].name").len(), 2);
+        assert_eq!(select("mods[port <= 80].port"), ["80i7", "9i4"]);
+        assert_eq!(select("mods[port = 80].port"), ["80i7"]);
+        assert!(select("items[5]").is_empty());
+        assert!(select("items[-4]").is_empty());
+        assert_eq!(select("..button.label"), ["\"ok\"", "\"go\""]);
+        assert_eq!(select("").len(), 1);
+
+        let mut root = root.clone();
+        let selector: Selector = "..label".parse().unwrap();
+        selector.select_mut(&mut root, |ast| {
+            *ast = Ast::Literal(Literal::Str("changed".into()))
+        });
+        let changed: Vec<_> = selector.select(&root).into_iter().map(text).collect();
+        assert_eq!(changed, ["\"changed\"", "\"changed\""]);
+
+        let selector: Selector = "servers.a.port".parse().unwrap();
+        let found = selector.select_all(vec![("one", &root), ("two", &root)]);
+        assert_eq!(
+            found.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            ["one", "two"]
+        );
+    }
+}
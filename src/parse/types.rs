@@ -1,4 +1,4 @@
-<<<<<<< HEAD
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::str::FromStr;
 
@@ -9,6 +9,7 @@ pub enum Keyword {
     Type,
     Super,
     Enum,
+    Pub,
 }
 
 impl Into<&'static str> for Keyword {
@@ -21,19 +22,99 @@ impl Into<&'static str> for Keyword {
             Type => "type",
             Super => "super",
             Enum => "enum",
+            Pub => "pub",
         }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum StaticType {
     Any,
+    Bool,
     Str,
     Selector,
     Unsigned(usize),
     Signed(usize),
     F32,
     F64,
+    /// A reference to a user-declared class, type or enum, with any type arguments.
+    Class(String, Vec<StaticType>),
+    List(Box<StaticType>),
+    Map(Box<StaticType>, Box<StaticType>),
+    /// `Tuple<A, B>`, or with no types, a bare `Tuple` of any length.
+    Tuple(Vec<StaticType>),
+    /// `Option<T>`
+    Optional(Box<StaticType>),
+}
+
+impl StaticType {
+    /// Applies `args` to the type named `name`, checking the arity of the built-in containers.
+    ///
+    /// The containers may also be named without arguments, in which case their parameters are
+    /// `Any` and a `Tuple` may have any number of items.
+    pub fn generic(name: &str, mut args: Vec<StaticType>) -> Result<Self, String> {
+        use StaticType::*;
+        let found = args.len();
+        let expect = |n: usize| {
+            if found == n || found == 0 {
+                Ok(())
+            } else {
+                Err(format!(
+                    "`{}` takes {} type arguments, found {}",
+                    name, n, found
+                ))
+            }
+        };
+        let mut arg = || {
+            if args.is_empty() {
+                Any
+            } else {
+                args.remove(0)
+            }
+        };
+        match name {
+            "List" => expect(1).map(|_| List(Box::new(arg()))),
+            "Option" => expect(1).map(|_| Optional(Box::new(arg()))),
+            "Map" => expect(2).map(|_| {
+                let key = arg();
+                Map(Box::new(key), Box::new(arg()))
+            }),
+            "Tuple" => Ok(Tuple(args)),
+            _ => match name.parse::<StaticType>().unwrap() {
+                Class(name, _) => Ok(Class(name, args)),
+                _ if found > 0 => Err(format!("`{}` doesn't take type arguments", name)),
+                ty => Ok(ty),
+            },
+        }
+    }
+
+    /// Replaces references to the type parameters in `params` with their arguments.
+    pub fn substitute(&self, params: &HashMap<String, StaticType>) -> Self {
+        use StaticType::*;
+        let sub = |t: &StaticType| Box::new(t.substitute(params));
+        match self {
+            Class(name, args) if args.is_empty() && params.contains_key(name) => {
+                params[name].clone()
+            }
+            Class(name, args) => Class(
+                name.clone(),
+                args.iter().map(|a| a.substitute(params)).collect(),
+            ),
+            List(t) => List(sub(t)),
+            Map(k, v) => Map(sub(k), sub(v)),
+            Tuple(ts) => Tuple(ts.iter().map(|t| t.substitute(params)).collect()),
+            Optional(t) => Optional(sub(t)),
+            t => t.clone(),
+        }
+    }
+}
+
+fn join(types: Vec<StaticType>) -> String {
+    types
+        .into_iter()
+        .map(Into::<String>::into)
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 impl TryInto<&'static str> for StaticType {
@@ -42,6 +123,7 @@ impl TryInto<&'static str> for StaticType {
         use StaticType::*;
         match self {
             Any => Ok("Any"),
+            Bool => Ok("bool"),
             Str => Ok("String"),
             Selector => Ok("Selector"),
             F32 => Ok("f32"),
@@ -57,11 +139,51 @@ impl Into<String> for StaticType {
         match self {
             Unsigned(s) => format!("u{}", s),
             Signed(s) => format!("i{}", s),
+            Class(name, args) if args.is_empty() => name,
+            Class(name, args) => format!("{}<{}>", name, join(args)),
+            List(t) => format!("List<{}>", join(vec![*t])),
+            Map(k, v) => format!("Map<{}>", join(vec![*k, *v])),
+            Tuple(ts) if ts.is_empty() => String::from("Tuple"),
+            Tuple(ts) => format!("Tuple<{}>", join(ts)),
+            Optional(t) => format!("Option<{}>", join(vec![*t])),
             _ => String::from_str(self.try_into().unwrap()).unwrap(),
         }
     }
 }
 
+impl FromStr for StaticType {
+    type Err = std::convert::Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use StaticType::*;
+        let sized = |prefix: char| -> Option<usize> {
+            if !s.starts_with(prefix) {
+                return None;
+            }
+            match s[1..].parse::<usize>() {
+                Ok(bits) if (1..=128).contains(&bits) => Some(bits),
+                _ => None,
+            }
+        };
+        Ok(match s {
+            "Any" => Any,
+            "bool" => Bool,
+            "String" => Str,
+            "Selector" => Selector,
+            "f32" => F32,
+            "f64" => F64,
+            _ => {
+                if let Some(bits) = sized('u') {
+                    Unsigned(bits)
+                } else if let Some(bits) = sized('i') {
+                    Signed(bits)
+                } else {
+                    Class(s.to_string(), Vec::new())
+                }
+            }
+        })
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum Integer {
     U8(u8),
@@ -101,107 +223,3 @@ into_num!(i16, Integer::I16);
 into_num!(i32, Integer::I32);
 into_num!(i64, Integer::I64);
 into_num!(i128, Integer::I128);
-=======
-use std::convert::TryInto;
-use std::str::FromStr;
-
-pub enum Keyword {
-    Use,
-    As,
-    Class,
-    Type,
-    Super,
-    Enum,
-}
-
-impl Into<&'static str> for Keyword {
-    fn into(self) -> &'static str {
-        use Keyword::*;
-        match self {
-            Use => "use",
-            As => "as",
-            Class => "class",
-            Type => "type",
-            Super => "super",
-            Enum => "enum",
-        }
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-pub enum StaticType {
-    Any,
-    Str,
-    Selector,
-    Unsigned(usize),
-    Signed(usize),
-    F32,
-    F64,
-}
-
-impl TryInto<&'static str> for StaticType {
-    type Error = ();
-    fn try_into(self) -> Result<&'static str, Self::Error> {
-        use StaticType::*;
-        match self {
-            Any => Ok("Any"),
-            Str => Ok("String"),
-            Selector => Ok("Selector"),
-            F32 => Ok("f32"),
-            F64 => Ok("f64"),
-            _ => Err(()),
-        }
-    }
-}
-
-impl Into<String> for StaticType {
-    fn into(self) -> String {
-        use StaticType::*;
-        match self {
-            Unsigned(s) => format!("u{}", s),
-            Signed(s) => format!("i{}", s),
-            _ => String::from_str(self.try_into().unwrap()).unwrap(),
-        }
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-pub enum Integer {
-    U8(u8),
-    U16(u16),
-    U32(u32),
-    U64(u64),
-    U128(u128),
-    I8(i8),
-    I16(i16),
-    I32(i32),
-    I64(i64),
-    I128(i128),
-}
-
-macro_rules! into_num {
-    ($num:ty, $match:path) => {
-        impl TryInto<$num> for Integer {
-            type Error = ::std::convert::Infallible;
-            fn try_into(self) -> Result<$num, Self::Error> {
-                if let $match(n) = self {
-                    Ok(n)
-                } else {
-                    unimplemented!("Into Num: {}", stringify!($num));
-                }
-            }
-        }
-    };
-}
-
-into_num!(u8, Integer::U8);
-into_num!(u16, Integer::U16);
-into_num!(u32, Integer::U32);
-into_num!(u64, Integer::U64);
-into_num!(u128, Integer::U128);
-into_num!(i8, Integer::I8);
-into_num!(i16, Integer::I16);
-into_num!(i32, Integer::I32);
-into_num!(i64, Integer::I64);
-into_num!(i128, Integer::I128);
->>>>>>> 98476a440fdd34cca12b2fea0108224ca3c2f6f2
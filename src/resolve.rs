@@ -0,0 +1,785 @@
+use crate::parse::grammar::{Document, ParseError};
+use crate::parse::{Ast, Import, Selector, Span};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum ResolveError {
+    Io {
+        url: String,
+        error: io::Error,
+    },
+    Parse {
+        url: String,
+        error: ParseError,
+    },
+    UnsupportedScheme(String),
+    /// Two `use`s in the document at `url` bind the same name.
+    DuplicateAlias {
+        url: String,
+        alias: String,
+        span: Span,
+    },
+    /// A `use` in the document at `url` names a definition that `import` doesn't have.
+    UnknownName {
+        url: String,
+        import: String,
+        name: String,
+        span: Span,
+    },
+    /// A `use` in the document at `url` names a definition that `import` doesn't declare `pub`.
+    Private {
+        url: String,
+        import: String,
+        name: String,
+        span: Span,
+    },
+    /// Each document in the cycle, with the span of the `use` that imports the next one.
+    Cycle(Vec<(String, Span)>),
+    /// The document at `url` has a `use`, but the loader doesn't allow imports.
+    ImportsDisabled {
+        url: String,
+        span: Span,
+    },
+    /// A `use` in the document at `url` would load more than `max` imported documents.
+    TooManyImports {
+        url: String,
+        max: usize,
+        span: Span,
+    },
+}
+
+impl Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use ResolveError::*;
+        match self {
+            Io { url, error } => write!(f, "couldn't load {}: {}", url, error),
+            Parse { url, error } => write!(f, "couldn't parse {}:\n{}", url, error),
+            UnsupportedScheme(url) => write!(f, "no resolver for {}", url),
+            DuplicateAlias { url, alias, span } => write!(
+                f,
+                "{}:{}..{}: `{}` is already imported",
+                url, span.start, span.end, alias
+            ),
+            UnknownName {
+                url,
+                import,
+                name,
+                span,
+            } => write!(
+                f,
+                "{}:{}..{}: {} has no definition `{}`",
+                url, span.start, span.end, import, name
+            ),
+            Private {
+                url,
+                import,
+                name,
+                span,
+            } => write!(
+                f,
+                "{}:{}..{}: `{}` is private to {}",
+                url, span.start, span.end, name, import
+            ),
+            Cycle(chain) => {
+                write!(f, "import cycle:")?;
+                for (url, span) in chain {
+                    write!(f, "\n  {}:{}..{} uses", url, span.start, span.end)?;
+                }
+                write!(f, "\n  {}", chain[0].0)
+            }
+            ImportsDisabled { url, span } => write!(
+                f,
+                "{}:{}..{}: imports aren't allowed",
+                url, span.start, span.end
+            ),
+            TooManyImports { url, max, span } => write!(
+                f,
+                "{}:{}..{}: more than {} documents imported",
+                url, span.start, span.end, max
+            ),
+        }
+    }
+}
+
+/// Collapses `.` and `..` segments so that each file has exactly one URL.
+///
+/// A `..` above the root is dropped, as it is by the filesystem, but one that leads out of a
+/// relative path is kept, so `../a.stn` and `a.stn` stay different files.
+pub fn normalize(url: &str) -> String {
+    let (scheme, path) = match url.find("://") {
+        Some(i) => url.split_at(i + 3),
+        None => ("", url),
+    };
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "." => (),
+            ".." => match parts.last() {
+                Some(&"") => (),
+                Some(&"..") | None => parts.push(part),
+                Some(_) => {
+                    parts.pop();
+                }
+            },
+            part => parts.push(part),
+        }
+    }
+    format!("{}{}", scheme, parts.join("/"))
+}
+
+/// Resolves `target` against the URL of the document importing it: targets without a scheme
+/// are paths relative to `base`.
+///
+/// Without a base, absolute paths become `file://` URLs and relative paths stay relative.
+pub fn locate(base: Option<&str>, target: &str) -> String {
+    if target.contains("://") {
+        return normalize(target);
+    }
+    match base {
+        Some(base) if !target.starts_with('/') => {
+            let dir = &base[..base.rfind('/').map_or(0, |i| i + 1)];
+            normalize(&format!("{}{}", dir, target))
+        }
+        _ if target.starts_with('/') => normalize(&format!("file://{}", target)),
+        _ => normalize(target),
+    }
+}
+
+/// Finds and loads the documents named by `use` statements.
+pub trait Resolver {
+    /// Turns the target of a `use` into an absolute URL.
+    ///
+    /// `base` is the URL of the importing document, or `None` for the document a build starts
+    /// from.
+    fn locate(&self, base: Option<&str>, target: &str) -> String {
+        locate(base, target)
+    }
+
+    /// Loads the contents of the document at `url`.
+    fn load(&mut self, url: &str) -> Result<Vec<u8>, ResolveError>;
+}
+
+/// Resolves `file://` URLs from the local filesystem.
+#[derive(Debug, Clone)]
+pub struct FileResolver {
+    /// The directory that relative paths are resolved against when there's no importing
+    /// document.
+    pub root: PathBuf,
+}
+
+impl FileResolver {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl Default for FileResolver {
+    fn default() -> Self {
+        Self::new(std::env::current_dir().unwrap_or_default())
+    }
+}
+
+impl Resolver for FileResolver {
+    fn locate(&self, base: Option<&str>, target: &str) -> String {
+        match base {
+            None if !target.contains("://") && !target.starts_with('/') => {
+                // A relative root would otherwise become the URL's host.
+                let root = std::env::current_dir().unwrap_or_default().join(&self.root);
+                let path = root.join(target);
+                normalize(&format!("file://{}", path.to_string_lossy()))
+            }
+            _ => locate(base, target),
+        }
+    }
+
+    fn load(&mut self, url: &str) -> Result<Vec<u8>, ResolveError> {
+        if !url.starts_with("file://") {
+            return Err(ResolveError::UnsupportedScheme(url.into()));
+        }
+        std::fs::read(&url["file://".len()..]).map_err(|error| ResolveError::Io {
+            url: url.into(),
+            error,
+        })
+    }
+}
+
+/// Resolves documents from a map of URLs to their contents, without touching the filesystem.
+///
+/// Documents are stored under the URL `locate` gives their path, so a file added as
+/// `lib/colors.stn` can be imported as `use colors.stn;` from `lib/main.stn`.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryResolver {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, path: &str, contents: impl Into<Vec<u8>>) {
+        self.files.insert(locate(None, path), contents.into());
+    }
+}
+
+impl<P: AsRef<str>, C: Into<Vec<u8>>> std::iter::FromIterator<(P, C)> for MemoryResolver {
+    fn from_iter<I: IntoIterator<Item = (P, C)>>(iter: I) -> Self {
+        let mut files = Self::new();
+        for (path, contents) in iter {
+            files.insert(path.as_ref(), contents);
+        }
+        files
+    }
+}
+
+impl Resolver for MemoryResolver {
+    fn load(&mut self, url: &str) -> Result<Vec<u8>, ResolveError> {
+        match self.files.get(url) {
+            Some(contents) => Ok(contents.clone()),
+            None => Err(ResolveError::Io {
+                url: url.into(),
+                error: io::ErrorKind::NotFound.into(),
+            }),
+        }
+    }
+}
+
+/// Embeds `.stn` files in the binary as a `MemoryResolver`, keyed by the paths given.
+///
+/// Paths are relative to the file invoking the macro, as with `include_bytes!`, so a set of
+/// files that import each other should be given relative to the same directory.
+///
+/// ```ignore
+/// let files = include_stn!("../data/main.stn", "../data/colors.stn");
+/// let mut loader = Loader::new(files);
+/// let main = loader.load("../data/main.stn")?;
+/// ```
+#[macro_export]
+macro_rules! include_stn {
+    ($($path:literal),* $(,)?) => {{
+        let mut files = $crate::resolve::MemoryResolver::new();
+        $(files.insert($path, &include_bytes!($path)[..]);)*
+        files
+    }};
+}
+
+/// A loaded document and the documents it imports.
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub url: String,
+    pub doc: Document<'static>,
+    /// The URL of each imported document, by the name it's bound to.
+    pub imports: HashMap<String, String>,
+    /// The URL of each imported definition and its name there, by the name it's bound to.
+    pub names: HashMap<String, (String, String)>,
+}
+
+impl Module {
+    /// Whether a `use` has already bound `name`, to a document or a definition.
+    pub fn binds(&self, name: &str) -> bool {
+        self.imports.contains_key(name) || self.names.contains_key(name)
+    }
+
+    /// The target of a `use` that would bind `name` if its document had been loaded, as for a
+    /// document added with `Loader::insert`. Glob imports can't be told apart, so aren't counted.
+    pub fn unloaded(&self, name: &str) -> Option<&str> {
+        self.doc.uses.iter().find_map(|node| match &node.ast {
+            Ast::Use(target, Import::Module(alias)) if !self.imports.contains_key(name) => {
+                let bound = alias.clone().unwrap_or_else(|| default_alias(target));
+                Some(target.as_str()).filter(|_| bound == name)
+            }
+            Ast::Use(target, Import::Names(names)) if !self.names.contains_key(name) => {
+                let mut bound = names.iter().map(|(n, alias)| alias.as_ref().unwrap_or(n));
+                Some(target.as_str()).filter(|_| bound.any(|n| n == name))
+            }
+            _ => None,
+        })
+    }
+}
+
+/// The name a `use` binds when it has no `as` clause: the imported file's name, without its
+/// extension.
+fn default_alias(url: &str) -> String {
+    let name = url.rsplit('/').next().unwrap_or(url);
+    match name.find('.') {
+        Some(0) | None => name.into(),
+        Some(i) => name[..i].into(),
+    }
+}
+
+fn visibility(doc: &Document) -> Vec<(String, bool)> {
+    doc.names()
+        .into_iter()
+        .map(|name| (name.to_string(), doc.is_public(name)))
+        .collect()
+}
+
+/// A document that's still loading its imports.
+#[derive(Debug)]
+struct Frame {
+    url: String,
+    /// The `use` in the previous frame that imported this document.
+    span: Span,
+    /// Whether the document only declares types, with no value or template of its own.
+    types_only: bool,
+    /// Each name the document defines, and whether it's public.
+    names: Vec<(String, bool)>,
+}
+
+/// Loads documents and, recursively, everything they import, parsing each URL only once.
+pub struct Loader<R> {
+    resolver: R,
+    modules: HashMap<String, Module>,
+    stack: Vec<Frame>,
+    allow_type_cycles: bool,
+    allow_imports: bool,
+    max_imports: Option<usize>,
+    /// How many documents have been loaded through `use`s.
+    imports: usize,
+}
+
+impl<R: Resolver> Loader<R> {
+    pub fn new(resolver: R) -> Self {
+        Self {
+            resolver,
+            modules: HashMap::new(),
+            stack: Vec::new(),
+            allow_type_cycles: false,
+            allow_imports: true,
+            max_imports: None,
+            imports: 0,
+        }
+    }
+
+    /// Permits import cycles in which every document only declares types.
+    pub fn allow_type_cycles(&mut self, allow: bool) {
+        self.allow_type_cycles = allow;
+    }
+
+    /// Whether documents may import others. When they can't, loading a document with a `use`
+    /// fails, so only the documents loaded directly are ever read.
+    pub fn allow_imports(&mut self, allow: bool) {
+        self.allow_imports = allow;
+    }
+
+    /// Bounds how many documents `use`s may load in total. Documents loaded directly, and
+    /// imports of documents that are already loaded, don't count.
+    pub fn limit_imports(&mut self, max: usize) {
+        self.max_imports = Some(max);
+    }
+
+    pub fn resolver(&self) -> &R {
+        &self.resolver
+    }
+
+    /// Loads the document at `target` and everything it imports, returning its URL.
+    pub fn load(&mut self, target: &str) -> Result<String, ResolveError> {
+        let url = self.resolver.locate(None, target);
+        self.load_url(&url, Span::default())?;
+        Ok(url)
+    }
+
+    /// Adds an already parsed document as `url`, without loading its imports, so only its own
+    /// values can be evaluated. Replaces any document already loaded from `url`.
+    pub fn insert(&mut self, url: &str, doc: Document<'static>) {
+        let module = Module {
+            url: url.into(),
+            doc,
+            imports: HashMap::new(),
+            names: HashMap::new(),
+        };
+        self.modules.insert(url.into(), module);
+    }
+
+    pub fn module(&self, url: &str) -> Option<&Module> {
+        self.modules.get(url)
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = &Module> {
+        self.modules.values()
+    }
+
+    /// Every value `selector` matches in the loaded documents, with the URL of the document it was
+    /// found in, ordered by URL.
+    pub fn select<'a>(&'a self, selector: &Selector) -> Vec<(&'a str, &'a Ast<'static>)> {
+        let mut modules: Vec<_> = self.modules.values().collect();
+        modules.sort_by(|a, b| a.url.cmp(&b.url));
+        selector.select_all(
+            modules
+                .into_iter()
+                .filter_map(|m| Some((m.url.as_str(), &m.doc.root.as_ref()?.ast))),
+        )
+    }
+
+    /// Checks whether importing `url` from the top of the stack would close a cycle.
+    ///
+    /// Returns `Ok(true)` for a permitted cycle, which mustn't be loaded again.
+    fn check_cycle(&self, url: &str, span: Span) -> Result<bool, ResolveError> {
+        let start = match self.stack.iter().position(|f| f.url == url) {
+            Some(start) => start,
+            None => return Ok(false),
+        };
+        let frames = &self.stack[start..];
+        if self.allow_type_cycles && frames.iter().all(|f| f.types_only) {
+            return Ok(true);
+        }
+        let spans = frames.iter().skip(1).map(|f| f.span).chain(Some(span));
+        Err(ResolveError::Cycle(
+            frames.iter().map(|f| f.url.clone()).zip(spans).collect(),
+        ))
+    }
+
+    fn load_url(&mut self, url: &str, span: Span) -> Result<(), ResolveError> {
+        if self.modules.contains_key(url) {
+            return Ok(());
+        }
+        let bytes = self.resolver.load(url)?;
+        let text = String::from_utf8(bytes).map_err(|e| ResolveError::Io {
+            url: url.into(),
+            error: io::Error::new(io::ErrorKind::InvalidData, e),
+        })?;
+        let doc = Document::parse(&text).map_err(|error| ResolveError::Parse {
+            url: url.into(),
+            error,
+        })?;
+        self.stack.push(Frame {
+            url: url.into(),
+            span,
+            types_only: doc.root.is_none()
+                && !doc.defs.iter().any(|d| matches!(d.ast, Ast::Template(_))),
+            names: visibility(&doc),
+        });
+        let mut module = Module {
+            url: url.into(),
+            doc,
+            imports: HashMap::new(),
+            names: HashMap::new(),
+        };
+        let result = self.load_imports(&mut module);
+        self.stack.pop();
+        result?;
+        self.modules.insert(url.into(), module);
+        Ok(())
+    }
+
+    /// The names defined by the document at `url`, which may still be loading, and whether
+    /// each is public.
+    fn names(&self, url: &str) -> Vec<(String, bool)> {
+        match self.modules.get(url) {
+            Some(module) => visibility(&module.doc),
+            None => self
+                .stack
+                .iter()
+                .find(|f| f.url == url)
+                .map(|f| f.names.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn load_imports(&mut self, module: &mut Module) -> Result<(), ResolveError> {
+        let url = module.url.clone();
+        for node in &module.doc.uses {
+            if let Ast::Use(target, import) = &node.ast {
+                if !self.allow_imports {
+                    return Err(ResolveError::ImportsDisabled {
+                        url,
+                        span: node.span,
+                    });
+                }
+                let target = self.resolver.locate(Some(&url), target);
+                if !self.check_cycle(&target, node.span)? {
+                    if !self.modules.contains_key(&target) {
+                        match self.max_imports {
+                            Some(max) if self.imports >= max => {
+                                return Err(ResolveError::TooManyImports {
+                                    url,
+                                    max,
+                                    span: node.span,
+                                })
+                            }
+                            _ => self.imports += 1,
+                        }
+                    }
+                    self.load_url(&target, node.span)?;
+                }
+                let duplicate = |alias: String| ResolveError::DuplicateAlias {
+                    url: url.clone(),
+                    alias,
+                    span: node.span,
+                };
+                match import {
+                    Import::Module(alias) => {
+                        let alias = alias.clone().unwrap_or_else(|| default_alias(&target));
+                        if module.binds(&alias) {
+                            return Err(duplicate(alias));
+                        }
+                        module.imports.insert(alias, target);
+                    }
+                    Import::Names(names) => {
+                        let defined = self.names(&target);
+                        for (name, alias) in names {
+                            match defined.iter().find(|(n, _)| n == name) {
+                                Some((_, true)) => (),
+                                Some((_, false)) => {
+                                    return Err(ResolveError::Private {
+                                        url,
+                                        import: target,
+                                        name: name.clone(),
+                                        span: node.span,
+                                    })
+                                }
+                                None => {
+                                    return Err(ResolveError::UnknownName {
+                                        url,
+                                        import: target,
+                                        name: name.clone(),
+                                        span: node.span,
+                                    })
+                                }
+                            }
+                            let alias = alias.clone().unwrap_or_else(|| name.clone());
+                            if module.binds(&alias) {
+                                return Err(duplicate(alias));
+                            }
+                            module.names.insert(alias, (target.clone(), name.clone()));
+                        }
+                    }
+                    Import::Glob => {
+                        for (name, _) in self.names(&target).into_iter().filter(|(_, p)| *p) {
+                            if module.binds(&name) {
+                                return Err(duplicate(name));
+                            }
+                            module.names.insert(name.clone(), (target.clone(), name));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls() {
+        assert_eq!(normalize("file:///a/./b/../c.stn"), "file:///a/c.stn");
+        assert_eq!(normalize("file:///../a.stn"), "file:///a.stn");
+        assert_eq!(normalize("a/../../b/./c.stn"), "../b/c.stn");
+        assert_eq!(locate(None, "a.stn"), "a.stn");
+        assert_eq!(locate(None, "./lib/../a.stn"), "a.stn");
+        assert_eq!(locate(None, "../a.stn"), "../a.stn");
+        assert_eq!(locate(None, "/srv/a.stn"), "file:///srv/a.stn");
+        assert_eq!(locate(Some("../lib/main.stn"), "../a.stn"), "../a.stn");
+        let files = FileResolver::new("/srv/stn");
+        assert_eq!(files.locate(None, "main.stn"), "file:///srv/stn/main.stn");
+        let relative = FileResolver::new("stn").locate(None, "main.stn");
+        assert!(relative.starts_with("file:///") && relative.ends_with("/stn/main.stn"));
+        assert_eq!(
+            files.locate(Some("file:///srv/stn/main.stn"), "../lib/colors.stn"),
+            "file:///srv/lib/colors.stn"
+        );
+        assert_eq!(
+            files.locate(Some("file:///srv/stn/main.stn"), "http://example.com/a.stn"),
+            "http://example.com/a.stn"
+        );
+        assert_eq!(default_alias("file:///srv/lib/colors.stn"), "colors");
+    }
+
+    #[test]
+    fn load_files() {
+        let dir = std::env::temp_dir().join(format!("architect-resolve-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(
+            dir.join("main.stn"),
+            "use lib/colors.stn;\nuse lib/sizes.stn as size;\n{ a: 1 }",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("lib/colors.stn"),
+            "use sizes.stn;\n{ red: \"#f00\" }",
+        )
+        .unwrap();
+        std::fs::write(dir.join("lib/sizes.stn"), "{ small: 4 }").unwrap();
+
+        let mut loader = Loader::new(FileResolver::new(&dir));
+        let main = loader.load("main.stn").unwrap();
+        let module = loader.module(&main).unwrap();
+        assert_eq!(module.imports.len(), 2);
+        assert!(module.imports["colors"].ends_with("/lib/colors.stn"));
+        assert_eq!(
+            module.imports["size"],
+            loader.module(&module.imports["colors"]).unwrap().imports["sizes"]
+        );
+        // `sizes.stn` is imported twice but only parsed once.
+        assert_eq!(loader.modules().count(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cycles() {
+        let files: MemoryResolver = vec![
+            ("file:///a.stn", "use b.stn;\n{ a: 1 }"),
+            ("file:///b.stn", "use c.stn;\nuse a.stn;\n{ b: 2 }"),
+            ("file:///c.stn", "{ c: 3 }"),
+            ("file:///x.stn", "use y.stn;\nclass X { y: y }"),
+            ("file:///y.stn", "use x.stn;\nclass Y { x: x }"),
+            ("file:///f.stn", "use g.stn;\npub f(x) = $x"),
+            ("file:///g.stn", "use f.stn;\nclass G { a: u8 }"),
+        ]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files);
+        match loader.load("/a.stn").unwrap_err() {
+            ResolveError::Cycle(chain) => {
+                let urls: Vec<_> = chain.iter().map(|(url, _)| url.as_str()).collect();
+                assert_eq!(urls, vec!["file:///a.stn", "file:///b.stn"]);
+                // The spans of `use b.stn;` in a.stn and `use a.stn;` in b.stn.
+                assert_eq!(chain[0].1, Span::new(0, 10));
+                assert_eq!(chain[1].1, Span::new(11, 21));
+            }
+            other => panic!("{}", other),
+        }
+        assert!(loader.load("/x.stn").is_err());
+        loader.allow_type_cycles(true);
+        assert!(loader.load("/a.stn").is_err());
+        let x = loader.load("/x.stn").unwrap();
+        let y = &loader.module(&x).unwrap().imports["y"];
+        assert_eq!(loader.module(y).unwrap().imports["x"], x);
+        // Templates are values, so a cycle through one isn't just between types.
+        assert!(matches!(
+            loader.load("/f.stn").unwrap_err(),
+            ResolveError::Cycle(_)
+        ));
+    }
+
+    #[test]
+    fn selective_imports() {
+        let files: MemoryResolver = vec![
+            (
+                "file:///main.stn",
+                "use lib.stn::{Color, palette as p};\nuse shapes.stn::*;\n{}",
+            ),
+            (
+                "file:///lib.stn",
+                "pub class Color { hex: String }\npub palette: { red: \"#f00\" }",
+            ),
+            (
+                "file:///shapes.stn",
+                "pub class Square { side: u8 }\npub class Circle { r: u8 }\nclass Arc { r: u8 }",
+            ),
+            (
+                "file:///clash.stn",
+                "use lib.stn::*;\nuse lib.stn::{palette};\n{}",
+            ),
+            ("file:///missing.stn", "use lib.stn::{blue};\n{}"),
+            ("file:///private.stn", "use shapes.stn::{Arc};\n{}"),
+        ]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files);
+        let main = loader.load("/main.stn").unwrap();
+        let names = &loader.module(&main).unwrap().names;
+        assert_eq!(names.len(), 4);
+        assert_eq!(names["p"], ("file:///lib.stn".into(), "palette".into()));
+        assert_eq!(names["Color"].1, "Color");
+        assert_eq!(names["Circle"].0, "file:///shapes.stn");
+        match loader.load("/clash.stn").unwrap_err() {
+            ResolveError::DuplicateAlias { alias, .. } => assert_eq!(alias, "palette"),
+            other => panic!("{}", other),
+        }
+        match loader.load("/missing.stn").unwrap_err() {
+            ResolveError::UnknownName { name, .. } => assert_eq!(name, "blue"),
+            other => panic!("{}", other),
+        }
+        match loader.load("/private.stn").unwrap_err() {
+            ResolveError::Private { name, .. } => assert_eq!(name, "Arc"),
+            other => panic!("{}", other),
+        }
+    }
+
+    #[test]
+    fn memory() {
+        let mut files = MemoryResolver::new();
+        files.insert("lib/main.stn", "use colors.stn::*;\n{ a: palette }");
+        files.insert("lib/colors.stn", &b"pub palette: { red: \"#f00\" }"[..]);
+        let mut loader = Loader::new(files);
+        let main = loader.load("lib/main.stn").unwrap();
+        assert_eq!(main, "lib/main.stn");
+        let names = &loader.module(&main).unwrap().names;
+        assert_eq!(names["palette"].0, "lib/colors.stn");
+        match loader.load("lib/sizes.stn").unwrap_err() {
+            ResolveError::Io { url, error } => {
+                assert_eq!(url, "lib/sizes.stn");
+                assert_eq!(error.kind(), io::ErrorKind::NotFound);
+            }
+            other => panic!("{}", other),
+        }
+
+        let files: MemoryResolver = vec![("a.stn", "{ n: 1 }"), ("../a.stn", "{ n: 2 }")]
+            .into_iter()
+            .collect();
+        let mut loader = Loader::new(files);
+        let near = loader.load("a.stn").unwrap();
+        let far = loader.load("../a.stn").unwrap();
+        assert_ne!(near, far);
+        assert_eq!(loader.modules().count(), 2);
+    }
+
+    #[test]
+    fn include_stn() {
+        let files = crate::include_stn!("../data/include/main.stn", "../data/include/colors.stn");
+        let mut loader = Loader::new(files);
+        let main = loader.load("../data/include/main.stn").unwrap();
+        let colors = loader.resolver().locate(None, "../data/include/colors.stn");
+        assert_eq!(loader.module(&main).unwrap().names["palette"].0, colors);
+        assert!(loader.module(&colors).unwrap().doc.is_public("palette"));
+    }
+
+    #[test]
+    fn import_limits() {
+        let files: MemoryResolver = vec![
+            (
+                "file:///main.stn",
+                "use a.stn;
+use b.stn;
+{}",
+            ),
+            (
+                "file:///a.stn",
+                "use b.stn;
+{ a: 1 }",
+            ),
+            ("file:///b.stn", "{ b: 2 }"),
+        ]
+        .into_iter()
+        .collect();
+        let mut loader = Loader::new(files.clone());
+        loader.allow_imports(false);
+        loader.load("/b.stn").unwrap();
+        match loader.load("/main.stn").unwrap_err() {
+            ResolveError::ImportsDisabled { url, .. } => assert_eq!(url, "file:///main.stn"),
+            other => panic!("{}", other),
+        }
+
+        // `b.stn` is imported twice, but only loaded once.
+        let mut loader = Loader::new(files.clone());
+        loader.limit_imports(2);
+        loader.load("/main.stn").unwrap();
+
+        let mut loader = Loader::new(files);
+        loader.limit_imports(1);
+        match loader.load("/main.stn").unwrap_err() {
+            ResolveError::TooManyImports { url, max, .. } => {
+                assert_eq!(url, "file:///a.stn");
+                assert_eq!(max, 1);
+            }
+            other => panic!("{}", other),
+        }
+    }
+}